@@ -0,0 +1,124 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::time::Duration;
+
+use crate::backend::error::Result;
+use crate::backend::paths;
+
+/// What happens to the clips copied from an application, see `AppRule`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AppAction {
+    /// Nothing is saved.
+    Ignore,
+    /// The clip is saved without the name and icon of the application.
+    Anonymize,
+    /// The clip is saved, then deleted this many seconds later.
+    ExpireAfter(u64),
+}
+
+impl AppAction {
+    /// How long an `ExpireAfter` clip is kept.
+    pub fn expires_after(&self) -> Option<Duration> {
+        match self {
+            AppAction::ExpireAfter(secs) => Some(Duration::from_secs(*secs)),
+            _ => None,
+        }
+    }
+}
+
+/// Which application a rule applies to.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AppMatcher {
+    /// The bundle identifier, e.g. `com.1password.1password`, case insensitive.
+    BundleId(String),
+    /// The exact name of the application, e.g. `KeePassXC`.
+    Name(String),
+}
+
+/// A capture rule of `app-rules.json`, e.g. `{ "bundle_id": "com.tinyspeck.slackmacgap", "action": { "expire_after": 60 } }`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AppRule {
+    #[serde(flatten)]
+    pub app: AppMatcher,
+    pub action: AppAction,
+}
+
+impl AppRule {
+    /// Whether the rule applies to the focused application.
+    ///
+    /// # Arguments
+    ///
+    /// * `bundle_id` - The bundle identifier of the application, `None` if it has none.
+    /// * `name` - The name of the application.
+    pub fn matches(&self, bundle_id: Option<&str>, name: &str) -> bool {
+        match &self.app {
+            AppMatcher::BundleId(id) => {
+                bundle_id.is_some_and(|bundle_id| bundle_id.eq_ignore_ascii_case(id))
+            }
+            AppMatcher::Name(app_name) => name == app_name,
+        }
+    }
+}
+
+/// The rules applied when `app-rules.json` does not exist: password managers are ignored.
+pub fn default_app_rules() -> Vec<AppRule> {
+    let ignore = |app: AppMatcher| AppRule {
+        app,
+        action: AppAction::Ignore,
+    };
+
+    vec![
+        ignore(AppMatcher::BundleId("com.apple.Passwords".to_string())),
+        ignore(AppMatcher::BundleId("com.apple.keychainaccess".to_string())),
+        ignore(AppMatcher::BundleId("com.bitwarden.desktop".to_string())),
+        ignore(AppMatcher::BundleId("com.1password.1password".to_string())),
+        ignore(AppMatcher::BundleId(
+            "com.agilebits.onepassword7".to_string(),
+        )),
+        ignore(AppMatcher::BundleId("org.keepassxc.keepassxc".to_string())),
+        ignore(AppMatcher::Name("Passwords".to_string())),
+        ignore(AppMatcher::Name("Keychain Access".to_string())),
+        ignore(AppMatcher::Name("Bitwarden".to_string())),
+        ignore(AppMatcher::Name("1Password".to_string())),
+        ignore(AppMatcher::Name("KeePassXC".to_string())),
+    ]
+}
+
+/// Reads the capture rules from `app-rules.json` in the data directory, see `paths::app_rules_path`.
+///
+/// The rules replace the defaults, see `default_app_rules`, which apply when the file does not exist.
+/// Rules are applied in order, the first rule matching an application wins.
+///
+/// Fails if the file cannot be read or parsed.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::app_rules::{load_app_rules, AppAction};
+///
+/// // app-rules.json: [{ "name": "Vault", "action": "ignore" }]
+/// let rules = load_app_rules()?;
+/// println!("{:?}", rules[0].action); // Output: Ignore
+/// ```
+pub fn load_app_rules() -> Result<Vec<AppRule>> {
+    match fs::read(paths::app_rules_path()?) {
+        Ok(json) => Ok(serde_json::from_slice(&json)?),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(default_app_rules()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// The action of the first rule matching the focused application, `None` if no rule does.
+pub fn action_for<'a>(
+    rules: &'a [AppRule],
+    bundle_id: Option<&str>,
+    name: &str,
+) -> Option<&'a AppAction> {
+    rules
+        .iter()
+        .find(|rule| rule.matches(bundle_id, name))
+        .map(|rule| &rule.action)
+}
@@ -0,0 +1,562 @@
+use base64::engine::general_purpose;
+use base64::prelude::*;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+use crate::backend::app_rules::{self, AppAction, AppRule};
+use crate::backend::clipboard::{ClipSource, ContentTypes, FileEntry, Item, Representation};
+use crate::backend::error::{Error, Result};
+use crate::backend::paths;
+use crate::backend::secrets::SecretScanner;
+use crate::backend::store::{ClipImport, ClipUsage, HistoryStore, RecordFilter};
+use crate::backend::utils::sha256_hex;
+
+/// CLI flag exporting the history to `<path>` and exiting, filtered by the `RecordFilter` flags.
+/// The export is a zip archive if the path ends with `.zip`, else a directory.
+pub const EXPORT_FLAG: &str = "--export";
+/// CLI flag importing the export at `<path>` and exiting.
+pub const IMPORT_FLAG: &str = "--import";
+/// CLI flags of the `RecordFilter` of an export, dates are `YYYY-MM-DD` (UTC) or RFC 3339.
+pub const SINCE_FLAG: &str = "--since";
+pub const UNTIL_FLAG: &str = "--until";
+pub const APP_FLAG: &str = "--app";
+pub const TYPE_FLAG: &str = "--type";
+/// CLI flag exporting the clips holding a secret too, see `Item::secrets`, they are left out by default.
+pub const INCLUDE_SECRETS_FLAG: &str = "--include-secrets";
+
+// Layout of an export, a directory or a zip archive:
+// one JSON object per line in `history.jsonl`, oldest record first,
+// images as PNG files in `images/`, named by their SHA-256 digest
+const HISTORY_FILE_NAME: &str = "history.jsonl";
+const IMAGES_DIR_NAME: &str = "images";
+// Where `history.jsonl` is written until it is added to a zip archive, next to the archive
+const HISTORY_SPOOL_EXTENSION: &str = "jsonl.partial";
+
+/// Runs the export or import requested by the CLI flags, `None` if there is none.
+///
+/// Returns a summary to print.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::archive;
+///
+/// // paste-fork --export history.zip --since 2025-12-01 --type image
+/// if let Some(result) = archive::run_from_args(store.as_ref()) {
+///     println!("{}", result?); // Output: Exported 12 records to "history.zip", unencrypted
+/// }
+/// ```
+pub fn run_from_args(store: &dyn HistoryStore) -> Option<Result<String>> {
+    if let Some(path) = paths::flag_value(EXPORT_FLAG) {
+        let path = PathBuf::from(path);
+
+        let include_secrets = env::args().skip(1).any(|arg| arg == INCLUDE_SECRETS_FLAG);
+
+        return Some(
+            filter_from_args()
+                .and_then(|filter| export_history(store, &path, &filter, include_secrets))
+                .map(|export| {
+                    let mut summary = format!(
+                        "Exported {} records to {:?}, unencrypted",
+                        export.exported, path
+                    );
+                    if export.secrets_left_out > 0 {
+                        summary.push_str(&format!(
+                            "\nLeft out {} records holding a secret, export them with {}",
+                            export.secrets_left_out, INCLUDE_SECRETS_FLAG
+                        ));
+                    }
+                    summary
+                }),
+        );
+    }
+
+    if let Some(path) = paths::flag_value(IMPORT_FLAG) {
+        let path = PathBuf::from(path);
+
+        return Some(
+            import_history(store, &path)
+                .map(|count| format!("Imported {} records from {:?}", count, path)),
+        );
+    }
+
+    None
+}
+
+/// What `export_history` has exported.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Export {
+    pub exported: usize,
+    /// The records holding a secret left out, see `INCLUDE_SECRETS_FLAG`.
+    pub secrets_left_out: usize,
+}
+
+/// Exports the records matching a filter, with all their representations and usage.
+///
+/// The export is not encrypted, the records holding a secret are left out unless asked for,
+/// they are then marked as such and stay out of the search index once imported.
+/// Records are written one at a time, the history is never loaded at once.
+/// Pinned records stay pinned once imported, pinboards are not exported.
+///
+/// # Arguments
+///
+/// * `store` - The history to export.
+/// * `path` - A `.zip` file, or a directory, created if needed.
+/// * `filter` - Which records to export.
+/// * `include_secrets` - Whether the records holding a secret are exported too.
+///
+/// # Errors
+/// Fails if the export cannot be written, or the history cannot be read.
+pub fn export_history(
+    store: &dyn HistoryStore,
+    path: &Path,
+    filter: &RecordFilter,
+    include_secrets: bool,
+) -> Result<Export> {
+    let mut archive = ArchiveWriter::create(path)?;
+    let mut export = Export::default();
+
+    // Oldest first, an import then numbers the records in the same order
+    for id in store.find_records(filter)? {
+        // Deleted since it was found
+        let Some(item) = store.get_record(id)? else {
+            continue;
+        };
+
+        if !item.secrets.is_empty() && !include_secrets {
+            export.secrets_left_out += 1;
+            continue;
+        }
+
+        let representations = store
+            .get_representations(item.id)?
+            .into_iter()
+            .map(|representation| ExportedRepresentation::new(representation, &mut archive))
+            .collect::<Result<Vec<_>>>()?;
+
+        archive.write_history_line(&ExportedClip::new(&item, representations))?;
+        export.exported += 1;
+    }
+
+    archive.finish()?;
+
+    Ok(export)
+}
+
+/// Imports an export of `export_history`, see `HistoryStore::import_clips`.
+///
+/// Records already in the history are merged, never duplicated, and keep their original timestamps.
+/// Records are screened like the clips copied live: the capture rules of their source app apply,
+/// see `app_rules::load_app_rules`, and secrets are dropped, redacted or expired, see `SecretScanner`.
+/// Malformed records are skipped and logged.
+/// The records are imported all at once, a failure imports none of them.
+/// Returns the number of records imported or merged.
+///
+/// # Arguments
+///
+/// * `store` - The history to import into.
+/// * `path` - A `.zip` file, or a directory, written by `export_history`.
+///
+/// # Errors
+/// Fails if the export or the capture rules cannot be read, or a record cannot be saved.
+pub fn import_history(store: &dyn HistoryStore, path: &Path) -> Result<usize> {
+    let mut archive = ArchiveReader::open(path)?;
+    let history = archive.read_file(HISTORY_FILE_NAME)?;
+    let history = String::from_utf8_lossy(&history);
+    let app_rules = app_rules::load_app_rules()?;
+    let secret_scanner = SecretScanner::default();
+
+    // Read one at a time, the images of the export are never loaded at once
+    let mut clips = history
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .filter_map(
+            |(index, line)| match ExportedClip::parse(line, &mut archive) {
+                Ok(clip) => screen(clip, &app_rules, &secret_scanner),
+                Err(err) => {
+                    log::warn!("Skipping line {} of {:?}: {}", index + 1, path, err);
+                    None
+                }
+            },
+        );
+
+    store.import_clips(&mut clips)
+}
+
+// ------------------------------------------------------------------
+//                             INTERNAL
+// ------------------------------------------------------------------
+
+/// Reads the filter of an export from the CLI flags, see `SINCE_FLAG`.
+///
+/// Fails if a date or type cannot be parsed.
+fn filter_from_args() -> Result<RecordFilter> {
+    let flag =
+        |name: &str| paths::flag_value(name).map(|value| value.to_string_lossy().to_string());
+
+    Ok(RecordFilter {
+        since: flag(SINCE_FLAG).map(|date| parse_date(&date)).transpose()?,
+        until: flag(UNTIL_FLAG).map(|date| parse_date(&date)).transpose()?,
+        source_app: flag(APP_FLAG),
+        content_type: flag(TYPE_FLAG)
+            .map(|name| {
+                ContentTypes::parse(&name)
+                    .ok_or_else(|| invalid_input(format!("unknown type {:?}", name)))
+            })
+            .transpose()?,
+    })
+}
+
+/// Applies the capture rule of the source app of an imported clip and looks for secrets in it,
+/// like `clipboard::listen` does for the clips copied live.
+///
+/// Returns `None` if the clip is not imported, ignored or dropped.
+fn screen(
+    mut clip: ClipImport,
+    app_rules: &[AppRule],
+    secret_scanner: &SecretScanner,
+) -> Option<ClipImport> {
+    // Exports have no bundle identifiers, only the rules by name apply
+    let action = app_rules::action_for(app_rules, None, &clip.source.app);
+    if action == Some(&AppAction::Ignore) {
+        log::info!(
+            "Skipping a clip copied from {}, an ignored app",
+            clip.source.app
+        );
+        return None;
+    }
+
+    let screening = secret_scanner.screen(&clip.representations);
+    let Some(representations) = screening.representations else {
+        log::info!(
+            "Skipping a clip holding a secret: {}",
+            screening.detections.join(", ")
+        );
+        return None;
+    };
+
+    if action == Some(&AppAction::Anonymize) {
+        clip.source = ClipSource::anonymous();
+    }
+    clip.representations = representations;
+    for detection in screening.detections {
+        if !clip.secrets.contains(&detection) {
+            clip.secrets.push(detection);
+        }
+    }
+    // The shortest of the app rule and secret TTLs applies, from the import on
+    clip.expires_at = [
+        action.and_then(AppAction::expires_after),
+        screening.expires_after,
+    ]
+    .into_iter()
+    .flatten()
+    .min()
+    .map(|expires_after| Utc::now() + expires_after);
+
+    Some(clip)
+}
+
+/// A line of `history.jsonl`, timestamps are RFC 3339.
+#[derive(Serialize, Deserialize)]
+struct ExportedClip {
+    source_app: String,
+    /// For readers of the export only, imported clips get the type of their representations.
+    content_type: String,
+    pinned: bool,
+    timestamp: String,
+    created_at: String,
+    last_copied_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_pasted_at: Option<String>,
+    copy_count: i64,
+    paste_count: i64,
+    /// The kinds of secrets found in the clip, see `Item::secrets`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    secrets: Vec<String>,
+    representations: Vec<ExportedRepresentation>,
+}
+
+/// A representation of an `ExportedClip`, its content is in exactly one of the optional fields.
+#[derive(Serialize, Deserialize)]
+struct ExportedRepresentation {
+    /// See `Representation::kind`.
+    kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    /// Binary contents, e.g. RTF.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    base64: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    files: Option<Vec<FileEntry>>,
+    /// The path of the PNG file of an image, relative to the export.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    image: Option<String>,
+}
+
+impl ExportedClip {
+    fn new(item: &Item, representations: Vec<ExportedRepresentation>) -> Self {
+        ExportedClip {
+            source_app: item.source_app.clone(),
+            content_type: item.content_type.as_str().to_string(),
+            pinned: item.pinned,
+            timestamp: item.timestamp.to_rfc3339(),
+            created_at: item.created_at.to_rfc3339(),
+            last_copied_at: item.last_copied_at.to_rfc3339(),
+            last_pasted_at: item
+                .last_pasted_at
+                .map(|last_pasted_at| last_pasted_at.to_rfc3339()),
+            copy_count: item.copy_count,
+            paste_count: item.paste_count,
+            secrets: item.secrets.clone(),
+            representations,
+        }
+    }
+
+    /// Parses a line of `history.jsonl`, reading its images from the export.
+    fn parse(line: &str, archive: &mut ArchiveReader) -> Result<ClipImport> {
+        let clip: ExportedClip = serde_json::from_str(line)?;
+
+        let representations = clip
+            .representations
+            .into_iter()
+            .map(|representation| representation.read(archive))
+            .filter_map(Result::transpose)
+            .collect::<Result<Vec<_>>>()?;
+        let usage = ClipUsage {
+            timestamp: parse_date(&clip.timestamp)?,
+            created_at: parse_date(&clip.created_at)?,
+            last_copied_at: parse_date(&clip.last_copied_at)?,
+            last_pasted_at: clip.last_pasted_at.as_deref().map(parse_date).transpose()?,
+            copy_count: clip.copy_count,
+            paste_count: clip.paste_count,
+        };
+        // Icons are not exported, reuse the icon cached for the application if any
+        let icon_path = paths::icons_dir()?.join(format!("{}.png", clip.source_app));
+        let source = ClipSource {
+            icon_path: if icon_path.exists() {
+                icon_path.to_string_lossy().to_string()
+            } else {
+                String::new()
+            },
+            app: clip.source_app,
+        };
+
+        Ok(ClipImport {
+            source,
+            representations,
+            usage,
+            pinned: clip.pinned,
+            secrets: clip.secrets,
+            expires_at: None,
+        })
+    }
+}
+
+impl ExportedRepresentation {
+    /// Exports a representation, writing images into the export.
+    fn new(representation: Representation, archive: &mut ArchiveWriter) -> Result<Self> {
+        let mut exported = ExportedRepresentation {
+            kind: representation.kind().to_string(),
+            text: None,
+            base64: None,
+            files: None,
+            image: None,
+        };
+
+        match representation {
+            Representation::Text(text) | Representation::Html(text) | Representation::Url(text) => {
+                exported.text = Some(text)
+            }
+            Representation::Rtf(bytes) => {
+                exported.base64 = Some(general_purpose::STANDARD.encode(bytes))
+            }
+            Representation::FileList(files) => exported.files = Some(files),
+            Representation::Image(png_bytes) => {
+                let name = format!("{}/{}.png", IMAGES_DIR_NAME, sha256_hex(&png_bytes));
+                archive.write_file(&name, &png_bytes)?;
+                exported.image = Some(name);
+            }
+        }
+
+        Ok(exported)
+    }
+
+    /// Reads the representation back, `None` for a kind written by a newer version.
+    fn read(self, archive: &mut ArchiveReader) -> Result<Option<Representation>> {
+        if let Some(files) = self.files {
+            return Ok(Some(Representation::FileList(files)));
+        }
+
+        let bytes = match (self.text, self.base64, self.image) {
+            (Some(text), _, _) => text.into_bytes(),
+            (_, Some(base64), _) => general_purpose::STANDARD
+                .decode(base64)
+                .map_err(|err| invalid_data(err.to_string()))?,
+            (_, _, Some(image)) => archive.read_file(&image)?,
+            _ => {
+                return Err(invalid_data(format!(
+                    "{} representation without content",
+                    self.kind
+                )))
+            }
+        };
+
+        Ok(Representation::from_bytes(&self.kind, bytes))
+    }
+}
+
+/// An export being written, a directory or a zip archive with the same layout.
+///
+/// `history.jsonl` is written line by line, next to the zip archive until `finish` adds it,
+/// a zip archive is written one file at a time.
+struct ArchiveWriter {
+    files: ArchiveFiles,
+    /// The files already written.
+    written: HashSet<String>,
+    history: BufWriter<File>,
+    history_path: PathBuf,
+}
+
+enum ArchiveFiles {
+    Dir(PathBuf),
+    Zip(ZipWriter<File>),
+}
+
+impl ArchiveWriter {
+    fn create(path: &Path) -> Result<Self> {
+        let (files, history_path) = if is_zip(path) {
+            (
+                ArchiveFiles::Zip(ZipWriter::new(File::create(path)?)),
+                path.with_extension(HISTORY_SPOOL_EXTENSION),
+            )
+        } else {
+            fs::create_dir_all(path.join(IMAGES_DIR_NAME))?;
+            (
+                ArchiveFiles::Dir(path.to_path_buf()),
+                path.join(HISTORY_FILE_NAME),
+            )
+        };
+
+        Ok(ArchiveWriter {
+            files,
+            written: HashSet::new(),
+            history: BufWriter::new(File::create(&history_path)?),
+            history_path,
+        })
+    }
+
+    /// Writes a file of the export, a file already written (e.g. the same image) is skipped.
+    fn write_file(&mut self, name: &str, bytes: &[u8]) -> Result<()> {
+        if !self.written.insert(name.to_string()) {
+            return Ok(());
+        }
+
+        match &mut self.files {
+            ArchiveFiles::Dir(dir) => fs::write(dir.join(name), bytes)?,
+            ArchiveFiles::Zip(zip) => {
+                zip.start_file(name, SimpleFileOptions::default())?;
+                zip.write_all(bytes)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Appends a clip to `history.jsonl`.
+    fn write_history_line(&mut self, clip: &ExportedClip) -> Result<()> {
+        serde_json::to_writer(&mut self.history, clip)?;
+        self.history.write_all(b"\n")?;
+
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        self.history.into_inner().map_err(|err| err.into_error())?;
+
+        if let ArchiveFiles::Zip(mut zip) = self.files {
+            let result = File::open(&self.history_path)
+                .map_err(Error::from)
+                .and_then(|mut history| {
+                    zip.start_file(HISTORY_FILE_NAME, SimpleFileOptions::default())?;
+                    io::copy(&mut history, &mut zip)?;
+                    Ok(())
+                });
+            let _ = fs::remove_file(&self.history_path);
+            result?;
+            zip.finish()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// An export being read, see `ArchiveWriter`.
+enum ArchiveReader {
+    Dir(PathBuf),
+    Zip(ZipArchive<File>),
+}
+
+impl ArchiveReader {
+    fn open(path: &Path) -> Result<Self> {
+        if is_zip(path) {
+            return Ok(ArchiveReader::Zip(ZipArchive::new(File::open(path)?)?));
+        }
+
+        Ok(ArchiveReader::Dir(path.to_path_buf()))
+    }
+
+    fn read_file(&mut self, name: &str) -> Result<Vec<u8>> {
+        // Names come from the export, never read outside of it
+        if Path::new(name)
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_)))
+        {
+            return Err(invalid_data(format!("invalid file name {:?}", name)));
+        }
+
+        match self {
+            ArchiveReader::Dir(dir) => Ok(fs::read(dir.join(name))?),
+            ArchiveReader::Zip(zip) => {
+                let mut bytes = Vec::new();
+                zip.by_name(name)?.read_to_end(&mut bytes)?;
+                Ok(bytes)
+            }
+        }
+    }
+}
+
+fn is_zip(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+}
+
+/// Parses a `YYYY-MM-DD` date (midnight UTC) or an RFC 3339 timestamp.
+fn parse_date(date: &str) -> Result<DateTime<Utc>> {
+    if let Ok(date) = NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+        return Ok(date
+            .and_hms_opt(0, 0, 0)
+            .expect("Midnight exists")
+            .and_utc());
+    }
+
+    DateTime::parse_from_rfc3339(date)
+        .map(|timestamp| timestamp.with_timezone(&Utc))
+        .map_err(|_| invalid_input(format!("invalid date {:?}", date)))
+}
+
+fn invalid_input(message: String) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidInput, message))
+}
+
+fn invalid_data(message: String) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidData, message))
+}
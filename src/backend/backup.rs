@@ -0,0 +1,246 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use once_cell::sync::Lazy;
+use std::cmp::Reverse;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::backend::crypto;
+use crate::backend::error::{Error, Result};
+use crate::backend::paths;
+use crate::backend::store::HistoryStore;
+
+/// CLI flag backing up the history and exiting.
+pub const BACKUP_FLAG: &str = "--backup";
+/// CLI flag restoring the backup at `<path>` and exiting.
+pub const RESTORE_FLAG: &str = "--restore";
+
+// Backups are named `clipboard-<UTC time>.db`, so sorting by name sorts by age
+const BACKUP_FILE_PREFIX: &str = "clipboard-";
+const BACKUP_FILE_EXTENSION: &str = "db";
+const BACKUP_TIME_FORMAT: &str = "%Y%m%d-%H%M%S-%3f";
+// Name of the copy of the encryption key written next to the backups
+const KEY_FILE_NAME: &str = "clipboard.key";
+
+// How often `run_backup_timer` checks whether a backup is due
+const BACKUP_CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+static BACKUP_POLICY: Lazy<RwLock<BackupPolicy>> =
+    Lazy::new(|| RwLock::new(BackupPolicy::default()));
+
+/// When and where backups are taken and how many of them are kept, see `run_backup_timer`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BackupPolicy {
+    /// How long after the latest backup a new one is taken, `None` disables scheduled backups.
+    pub interval: Option<Duration>,
+    /// How many backups are kept, the oldest are deleted first.
+    pub keep: usize,
+    /// The backups directory, `None` for the default one, see `paths::backups_dir`.
+    pub dir: Option<PathBuf>,
+    /// Whether a copy of the encryption key is written into the backups directory, see `crypto::export_key`.
+    pub include_key: bool,
+}
+
+impl Default for BackupPolicy {
+    /// A backup a day, kept for a week, without the key.
+    fn default() -> Self {
+        BackupPolicy {
+            interval: Some(Duration::from_secs(24 * 60 * 60)),
+            keep: 7,
+            dir: None,
+            include_key: false,
+        }
+    }
+}
+
+/// A backup in the backups directory, see `backups_dir`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Backup {
+    pub path: PathBuf,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Update the backup policy, e.g. from the settings, it applies from the next check of `run_backup_timer`.
+pub fn set_backup_policy(policy: BackupPolicy) {
+    *BACKUP_POLICY.write().unwrap() = policy;
+}
+
+pub fn backup_policy() -> BackupPolicy {
+    BACKUP_POLICY.read().unwrap().clone()
+}
+
+/// Backs up the history into the backups directory, then deletes the backups beyond the policy.
+///
+/// The encryption key is written next to the backups if the policy says so.
+/// Returns the new backup.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::backup::backup_now;
+///
+/// println!("{:?}", backup_now(store.as_ref())?.path); // Output: "/Users/foo/Library/Application Support/paste-fork/backups/clipboard-20251227-171128-042.db"
+/// ```
+pub fn backup_now(store: &dyn HistoryStore) -> Result<Backup> {
+    let backup = write_backup(store)?;
+    delete_old_backups()?;
+
+    if backup_policy().include_key {
+        let path = backups_dir()?.join(KEY_FILE_NAME);
+        crypto::export_key(&path)?;
+    }
+
+    Ok(backup)
+}
+
+/// The backups of the backups directory, newest first.
+///
+/// Other files of the directory are ignored.
+pub fn list_backups() -> Result<Vec<Backup>> {
+    let mut backups = Vec::new();
+
+    for entry in fs::read_dir(backups_dir()?)? {
+        let path = entry?.path();
+
+        if path
+            .extension()
+            .is_none_or(|ext| ext != BACKUP_FILE_EXTENSION)
+        {
+            continue;
+        }
+
+        let created_at = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.strip_prefix(BACKUP_FILE_PREFIX))
+            .and_then(|time| NaiveDateTime::parse_from_str(time, BACKUP_TIME_FORMAT).ok())
+            .map(|naive| naive.and_utc());
+
+        if let Some(created_at) = created_at {
+            backups.push(Backup { path, created_at });
+        }
+    }
+
+    backups.sort_by_key(|backup| Reverse(backup.created_at));
+
+    Ok(backups)
+}
+
+/// Replaces the history with a backup, see `HistoryStore::restore`.
+///
+/// The current history is backed up first, restoring that backup undoes the restore.
+/// Callers should reload everything read from the store.
+///
+/// # Arguments
+///
+/// * `store` - The history to replace.
+/// * `path` - The backup file, in the backups directory or anywhere else.
+pub fn restore_backup(store: &dyn HistoryStore, path: &Path) -> Result<()> {
+    // Old backups are deleted once restored, the backup to restore may be the oldest
+    let current = write_backup(store)?;
+
+    store.restore(path)?;
+    log::info!(
+        "Restored the clipboard history from {:?}, the previous history is in {:?}",
+        path,
+        current.path
+    );
+
+    delete_old_backups()
+}
+
+/// Takes a backup whenever the latest one is older than the interval of the backup policy.
+///
+/// Failures are sent to the UI to be reported.
+/// Returns when the UI is gone, or at once if the store cannot be backed up, see `MemoryStore`.
+pub fn run_backup_timer(store: Arc<dyn HistoryStore>, tx: mpsc::UnboundedSender<Result<()>>) {
+    loop {
+        if let Some(interval) = backup_policy().interval {
+            let latest =
+                list_backups().map(|backups| backups.first().map(|backup| backup.created_at));
+            let is_due = match latest {
+                Ok(Some(created_at)) => Utc::now()
+                    .signed_duration_since(created_at)
+                    .to_std()
+                    .is_ok_and(|age| age >= interval),
+                Ok(None) | Err(_) => true,
+            };
+
+            if is_due {
+                match backup_now(store.as_ref()) {
+                    Ok(_) => {}
+                    Err(Error::Io(err)) if err.kind() == io::ErrorKind::Unsupported => {
+                        log::info!("Scheduled backups are disabled: {}", err);
+                        return;
+                    }
+                    Err(err) => {
+                        log::error!("Failed to back up the clipboard history: {}", err);
+                        if tx.send(Err(err)).is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+
+        thread::sleep(BACKUP_CHECK_INTERVAL);
+    }
+}
+
+/// Runs the backup or restore requested by the CLI flags, `None` if there is none.
+///
+/// Returns a summary to print, see `archive::run_from_args`.
+pub fn run_from_args(store: &dyn HistoryStore) -> Option<Result<String>> {
+    if let Some(path) = paths::flag_value(RESTORE_FLAG) {
+        let path = PathBuf::from(path);
+
+        return Some(
+            restore_backup(store, &path).map(|()| format!("Restored the history from {:?}", path)),
+        );
+    }
+
+    if env::args().skip(1).any(|arg| arg == BACKUP_FLAG) {
+        return Some(
+            backup_now(store).map(|backup| format!("Backed up the history to {:?}", backup.path)),
+        );
+    }
+
+    None
+}
+
+// ------------------------------------------------------------------
+//                             INTERNAL
+// ------------------------------------------------------------------
+fn write_backup(store: &dyn HistoryStore) -> Result<Backup> {
+    let created_at = Utc::now();
+    let path = backups_dir()?.join(format!(
+        "{}{}.{}",
+        BACKUP_FILE_PREFIX,
+        created_at.format(BACKUP_TIME_FORMAT),
+        BACKUP_FILE_EXTENSION
+    ));
+
+    store.backup(&path)?;
+    log::info!("Backed up the clipboard history to {:?}", path);
+
+    Ok(Backup { path, created_at })
+}
+
+/// The backups directory of the backup policy, see `paths::backups_dir`.
+fn backups_dir() -> io::Result<PathBuf> {
+    paths::backups_dir(backup_policy().dir.as_deref())
+}
+
+/// Deletes the oldest backups beyond the `keep` of the backup policy.
+fn delete_old_backups() -> Result<()> {
+    for backup in list_backups()?.iter().skip(backup_policy().keep.max(1)) {
+        fs::remove_file(&backup.path)?;
+        log::info!("Deleted old backup {:?}", backup.path);
+    }
+
+    Ok(())
+}
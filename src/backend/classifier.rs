@@ -0,0 +1,610 @@
+use std::fmt;
+
+// Keywords hinting at a programming language, a snippet needs `MIN_LANGUAGE_HITS` of them
+// A tie goes to the language listed first, so supersets (TypeScript over JavaScript) are listed last
+const LANGUAGE_KEYWORDS: &[(&str, &[&str])] = &[
+    (
+        "Rust",
+        &[
+            "fn ",
+            "let mut ",
+            "impl ",
+            "pub fn ",
+            "use std::",
+            "#[derive",
+            "-> ",
+            "::new(",
+            "match ",
+            "&self",
+        ],
+    ),
+    (
+        "Python",
+        &[
+            "def ", "import ", "from ", "self.", "elif ", "print(", "__init__", "None", "lambda ",
+        ],
+    ),
+    (
+        "JavaScript",
+        &[
+            "function ",
+            "const ",
+            "let ",
+            "=> ",
+            "console.log",
+            "require(",
+            "document.",
+            "===",
+            "undefined",
+        ],
+    ),
+    (
+        "TypeScript",
+        &[
+            "interface ",
+            ": string",
+            ": number",
+            ": boolean",
+            "readonly ",
+            "export type ",
+            "<T>",
+        ],
+    ),
+    (
+        "Go",
+        &[
+            "func ",
+            "package ",
+            ":= ",
+            "fmt.",
+            "defer ",
+            "chan ",
+            "err != nil",
+        ],
+    ),
+    (
+        "Java",
+        &[
+            "public class ",
+            "public static void",
+            "System.out",
+            "private ",
+            "import java.",
+            "new ",
+            "@Override",
+        ],
+    ),
+    (
+        "C",
+        &[
+            "#include", "int main", "printf(", "malloc(", "sizeof(", "NULL", "struct ",
+        ],
+    ),
+    (
+        "C++",
+        &[
+            "#include",
+            "std::",
+            "int main",
+            "cout <<",
+            "nullptr",
+            "template<",
+            "namespace ",
+        ],
+    ),
+    (
+        "Swift",
+        &[
+            "func ",
+            "let ",
+            "var ",
+            "guard ",
+            "import UIKit",
+            "import SwiftUI",
+            "-> ",
+            "?? ",
+        ],
+    ),
+    (
+        "Shell",
+        &[
+            "#!/bin/", "sudo ", "echo ", "export ", " | ", "&& ", "$(", "fi\n", "done\n",
+        ],
+    ),
+    (
+        "SQL",
+        &[
+            "SELECT ",
+            "FROM ",
+            "WHERE ",
+            "INSERT INTO",
+            "CREATE TABLE",
+            "UPDATE ",
+            "JOIN ",
+            "GROUP BY",
+        ],
+    ),
+    (
+        "HTML",
+        &[
+            "<div",
+            "<span",
+            "</",
+            "<!DOCTYPE",
+            "<html",
+            "class=\"",
+            "href=\"",
+        ],
+    ),
+    (
+        "CSS",
+        &[
+            "{\n", "px;", "color:", "margin:", "padding:", "display:", "font-",
+        ],
+    ),
+];
+const MIN_LANGUAGE_HITS: usize = 2;
+// Keyword hits making a snippet code even if it is not shaped like code, e.g. a single statement
+const CODE_LANGUAGE_HITS: usize = 3;
+// CSS properties taking a color, a hex color of digits only is a color in their declarations only, e.g. `color: #333;`
+const COLOR_PROPERTIES: &[&str] = &["color", "background", "fill", "stroke"];
+
+/// What a text clip holds, detected when it is saved.
+///
+/// `Prose` is the fallback for anything not recognized.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ContentSubtype {
+    Url,
+    Email,
+    /// A hex (`#rrggbb`) or functional (`rgb()`, `hsl()`) CSS color, alone or in a declaration (`color: #fff;`).
+    ///
+    /// A hex color of digits only, e.g. `#123`, is taken for an issue number unless it is declared.
+    Color,
+    FilePath,
+    Json,
+    /// Source code, with the language if it could be guessed.
+    Code(Option<String>),
+    Phone,
+    Number,
+    Prose,
+}
+
+impl ContentSubtype {
+    /// The `subtype` column of the `history` table.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentSubtype::Url => "URL",
+            ContentSubtype::Email => "EMAIL",
+            ContentSubtype::Color => "COLOR",
+            ContentSubtype::FilePath => "PATH",
+            ContentSubtype::Json => "JSON",
+            ContentSubtype::Code(_) => "CODE",
+            ContentSubtype::Phone => "PHONE",
+            ContentSubtype::Number => "NUMBER",
+            ContentSubtype::Prose => "PROSE",
+        }
+    }
+
+    /// Inverse of `as_str`, case insensitive, `language` only applies to `CODE`.
+    pub fn parse(subtype: &str, language: Option<String>) -> Option<Self> {
+        match subtype.to_ascii_uppercase().as_str() {
+            "URL" => Some(ContentSubtype::Url),
+            "EMAIL" => Some(ContentSubtype::Email),
+            "COLOR" => Some(ContentSubtype::Color),
+            "PATH" => Some(ContentSubtype::FilePath),
+            "JSON" => Some(ContentSubtype::Json),
+            "CODE" => Some(ContentSubtype::Code(language)),
+            "PHONE" => Some(ContentSubtype::Phone),
+            "NUMBER" => Some(ContentSubtype::Number),
+            "PROSE" => Some(ContentSubtype::Prose),
+            _ => None,
+        }
+    }
+
+    /// The guessed language of `Code`.
+    pub fn language(&self) -> Option<&str> {
+        match self {
+            ContentSubtype::Code(language) => language.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ContentSubtype {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContentSubtype::Url => write!(f, "URL"),
+            ContentSubtype::Email => write!(f, "Email"),
+            ContentSubtype::Color => write!(f, "Color"),
+            ContentSubtype::FilePath => write!(f, "Path"),
+            ContentSubtype::Json => write!(f, "JSON"),
+            ContentSubtype::Code(Some(language)) => write!(f, "{}", language),
+            ContentSubtype::Code(None) => write!(f, "Code"),
+            ContentSubtype::Phone => write!(f, "Phone"),
+            ContentSubtype::Number => write!(f, "Number"),
+            ContentSubtype::Prose => write!(f, "Text"),
+        }
+    }
+}
+
+/// Detects what a text holds.
+///
+/// Single-line values (URL, email, color, path, number, phone) must span the whole text,
+/// so a sentence containing a URL is still prose.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::classifier::{classify, ContentSubtype};
+///
+/// assert_eq!(classify("https://dioxuslabs.com"), ContentSubtype::Url);
+/// assert_eq!(classify("fn main() {\n    let mut x = 1;\n}"), ContentSubtype::Code(Some("Rust".to_string())));
+/// ```
+pub fn classify(text: &str) -> ContentSubtype {
+    let text = text.trim();
+
+    if text.is_empty() {
+        return ContentSubtype::Prose;
+    }
+
+    if !text.contains('\n') {
+        if is_url(text) {
+            return ContentSubtype::Url;
+        }
+        if is_email(text) {
+            return ContentSubtype::Email;
+        }
+        if color_value(text).is_some() {
+            return ContentSubtype::Color;
+        }
+        if is_file_path(text) {
+            return ContentSubtype::FilePath;
+        }
+        if is_number(text) {
+            return ContentSubtype::Number;
+        }
+        if is_phone(text) {
+            return ContentSubtype::Phone;
+        }
+    }
+
+    if is_json(text) {
+        return ContentSubtype::Json;
+    }
+
+    // Keywords alone are weak evidence, prose says "import" and "from" too
+    let language = guess_language(text);
+
+    if looks_like_code(text) || language.is_some_and(|(_, hits)| hits >= CODE_LANGUAGE_HITS) {
+        return ContentSubtype::Code(language.map(|(language, _)| language.to_string()));
+    }
+
+    ContentSubtype::Prose
+}
+
+/// The color a text classified as `ContentSubtype::Color` holds, without the property of a declaration.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::classifier::color_value;
+///
+/// assert_eq!(color_value("color: #333;"), Some("#333"));
+/// assert_eq!(color_value("rgb(255, 136, 0)"), Some("rgb(255, 136, 0)"));
+/// assert_eq!(color_value("#123"), None);
+/// ```
+pub fn color_value(text: &str) -> Option<&str> {
+    let text = text.trim();
+    let declaration = text.strip_suffix(';').unwrap_or(text).split_once(':');
+
+    let (value, declared) = match declaration {
+        Some((property, value))
+            if COLOR_PROPERTIES
+                .iter()
+                .any(|color_property| property.trim().ends_with(color_property)) =>
+        {
+            (value.trim(), true)
+        }
+        _ => (text, false),
+    };
+
+    is_color_value(value, declared).then_some(value)
+}
+
+// ------------------------------------------------------------------
+//                             INTERNAL
+// ------------------------------------------------------------------
+fn is_url(text: &str) -> bool {
+    if text.contains(char::is_whitespace) {
+        return false;
+    }
+
+    if let Some((scheme, rest)) = text.split_once("://") {
+        return !scheme.is_empty()
+            && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+            && !rest.is_empty();
+    }
+
+    text.strip_prefix("www.")
+        .is_some_and(|host| host.contains('.') && !host.starts_with('.'))
+}
+
+fn is_email(text: &str) -> bool {
+    let text = text.strip_prefix("mailto:").unwrap_or(text);
+    let Some((local, domain)) = text.split_once('@') else {
+        return false;
+    };
+
+    !local.is_empty()
+        && local
+            .chars()
+            .all(|c| c.is_alphanumeric() || "._%+-".contains(c))
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && domain
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '.' || c == '-')
+}
+
+/// Whether a CSS value is a color, see `ContentSubtype::Color`.
+///
+/// `declared` - Whether the value is declared for a color property, any hex color is then accepted.
+fn is_color_value(text: &str, declared: bool) -> bool {
+    if let Some(hex) = text.strip_prefix('#') {
+        return matches!(hex.len(), 3 | 4 | 6 | 8)
+            && hex.chars().all(|c| c.is_ascii_hexdigit())
+            && (declared || hex.contains(|c: char| c.is_ascii_alphabetic()));
+    }
+
+    let lower = text.to_ascii_lowercase();
+    ["rgb(", "rgba(", "hsl(", "hsla("].iter().any(|function| {
+        lower
+            .strip_prefix(function)
+            .and_then(|args| args.strip_suffix(')'))
+            .is_some_and(|args| {
+                let args = args.split([',', ' ', '/']).filter(|arg| !arg.is_empty());
+                let count = args
+                    .clone()
+                    .filter(|arg| {
+                        arg.trim_end_matches(['%', 'g', 'd', 'e'])
+                            .parse::<f64>()
+                            .is_ok()
+                    })
+                    .count();
+                (3..=4).contains(&count) && count == args.count()
+            })
+    })
+}
+
+fn is_file_path(text: &str) -> bool {
+    let is_windows_path =
+        text.len() > 3 && text.as_bytes()[0].is_ascii_alphabetic() && text[1..].starts_with(":\\");
+
+    !text.contains("://")
+        && text.len() > 1
+        && (is_windows_path
+            || ["/", "~/", "./", "../"]
+                .iter()
+                .any(|prefix| text.starts_with(prefix)))
+}
+
+fn is_number(text: &str) -> bool {
+    // A leading `+` is left to phone numbers, e.g. `+15551234567`
+    let digits = text
+        .strip_prefix('-')
+        .unwrap_or(text)
+        .replace([',', '_'], "");
+
+    digits.starts_with(|c: char| c.is_ascii_digit()) && digits.parse::<f64>().is_ok()
+}
+
+fn is_phone(text: &str) -> bool {
+    let digits = text.chars().filter(char::is_ascii_digit).count();
+
+    // Dates and IPv4 addresses have as many digits and no other characters
+    !is_date(text)
+        && !is_ipv4_address(text)
+        && (7..=15).contains(&digits)
+        && text
+            .chars()
+            .all(|c| c.is_ascii_digit() || " +-().".contains(c))
+        && text
+            .trim_start_matches('+')
+            .starts_with(|c: char| c.is_ascii_digit() || c == '(')
+}
+
+/// Whether a text is a `2025-12-27` or `27.12.2025` date.
+fn is_date(text: &str) -> bool {
+    let lens = |separator: char| {
+        text.split(separator)
+            .map(|part| {
+                part.chars()
+                    .all(|c| c.is_ascii_digit())
+                    .then_some(part.len())
+            })
+            .collect::<Option<Vec<_>>>()
+            .unwrap_or_default()
+    };
+
+    lens('-') == [4, 2, 2] || matches!(lens('.')[..], [1 | 2, 1 | 2, 4])
+}
+
+/// Whether a text is a dotted quad, e.g. `192.168.1.1`.
+fn is_ipv4_address(text: &str) -> bool {
+    let octets = text.split('.').collect::<Vec<_>>();
+
+    octets.len() == 4
+        && octets.iter().all(|octet| {
+            (1..=3).contains(&octet.len())
+                && octet.chars().all(|c| c.is_ascii_digit())
+                && octet.parse::<u8>().is_ok()
+        })
+}
+
+fn is_json(text: &str) -> bool {
+    (text.starts_with('{') || text.starts_with('['))
+        && serde_json::from_str::<serde_json::Value>(text).is_ok()
+}
+
+/// The language with the most keyword hits and its hits, if it has at least `MIN_LANGUAGE_HITS`.
+fn guess_language(text: &str) -> Option<(&'static str, usize)> {
+    LANGUAGE_KEYWORDS
+        .iter()
+        .map(|(language, keywords)| {
+            let hits = keywords
+                .iter()
+                .filter(|keyword| text.contains(*keyword))
+                .count();
+            (language, hits)
+        })
+        .filter(|(_, hits)| *hits >= MIN_LANGUAGE_HITS)
+        // The first listed language wins a tie, `max_by_key` would pick the last one
+        .fold(None, |best, (language, hits)| match best {
+            Some((_, best_hits)) if best_hits >= hits => best,
+            _ => Some((*language, hits)),
+        })
+}
+
+/// Whether most lines end like statements or blocks do.
+fn looks_like_code(text: &str) -> bool {
+    let lines = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>();
+
+    // Prose rarely ends with a semicolon or a brace
+    if lines.len() == 1 {
+        return lines[0].ends_with([';', '{', '}']);
+    }
+
+    let code_lines = lines
+        .iter()
+        .filter(|line| line.ends_with([';', '{', '}', ')', ',']) || line.starts_with("//"))
+        .count();
+
+    code_lines * 2 > lines.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_single_line_values() {
+        assert_eq!(classify("https://dioxuslabs.com"), ContentSubtype::Url);
+        assert_eq!(classify("www.rust-lang.org"), ContentSubtype::Url);
+        assert_eq!(
+            classify("mailto:ferris@rust-lang.org"),
+            ContentSubtype::Email
+        );
+        assert_eq!(classify("~/Library/Logs"), ContentSubtype::FilePath);
+        assert_eq!(classify("C:\\Users\\ferris"), ContentSubtype::FilePath);
+        assert_eq!(
+            classify("  visit https://dioxuslabs.com  "),
+            ContentSubtype::Prose
+        );
+    }
+
+    #[test]
+    fn classifies_colors() {
+        assert_eq!(classify("#ff8800"), ContentSubtype::Color);
+        assert_eq!(classify("#FA0C"), ContentSubtype::Color);
+        assert_eq!(classify("rgba(255, 136, 0, 0.5)"), ContentSubtype::Color);
+        assert_eq!(classify("hsl(120deg 50% 50%)"), ContentSubtype::Color);
+        assert_eq!(classify("color: #333;"), ContentSubtype::Color);
+        assert_eq!(classify("background-color: #1a2b3c"), ContentSubtype::Color);
+    }
+
+    #[test]
+    fn extracts_color_values() {
+        assert_eq!(color_value("color: #333;"), Some("#333"));
+        assert_eq!(
+            color_value("  border-color : rgb(0 0 0);"),
+            Some("rgb(0 0 0)")
+        );
+        assert_eq!(color_value("#ff8800"), Some("#ff8800"));
+        assert_eq!(color_value("width: 12px"), None);
+    }
+
+    #[test]
+    fn takes_hex_digits_for_issue_numbers() {
+        assert_ne!(classify("#123"), ContentSubtype::Color);
+        assert_ne!(classify("#1234"), ContentSubtype::Color);
+        assert_ne!(classify("#123456"), ContentSubtype::Color);
+        assert_ne!(classify("width: #123"), ContentSubtype::Color);
+        assert_ne!(classify("#ggg"), ContentSubtype::Color);
+        assert_ne!(classify("rgb(1, 2)"), ContentSubtype::Color);
+    }
+
+    #[test]
+    fn tells_numbers_from_phone_numbers() {
+        assert_eq!(classify("42"), ContentSubtype::Number);
+        assert_eq!(classify("-1,234.5"), ContentSubtype::Number);
+        assert_eq!(classify("1_000_000"), ContentSubtype::Number);
+        assert_eq!(classify("+15551234567"), ContentSubtype::Phone);
+        assert_eq!(classify("+1 (555) 123-4567"), ContentSubtype::Phone);
+        assert_eq!(classify("555-1234"), ContentSubtype::Phone);
+        assert_eq!(classify("+42"), ContentSubtype::Prose);
+        assert_eq!(classify("2025-12-27"), ContentSubtype::Prose);
+        assert_eq!(classify("27.12.2025"), ContentSubtype::Prose);
+        assert_eq!(classify("192.168.1.1"), ContentSubtype::Prose);
+        assert_eq!(classify("555.123.4567"), ContentSubtype::Phone);
+    }
+
+    #[test]
+    fn classifies_json_and_code() {
+        assert_eq!(classify("{\"name\": \"paste\"}"), ContentSubtype::Json);
+        assert_eq!(classify("[1, 2, 3]"), ContentSubtype::Json);
+        assert_eq!(
+            classify("fn main() {\n    let mut x = 1;\n}"),
+            ContentSubtype::Code(Some("Rust".to_string()))
+        );
+        assert_eq!(
+            classify("SELECT id FROM history WHERE pinned = 1 GROUP BY app"),
+            ContentSubtype::Code(Some("SQL".to_string()))
+        );
+        assert_eq!(
+            classify("const add = (a, b) => a + b;"),
+            ContentSubtype::Code(Some("JavaScript".to_string()))
+        );
+    }
+
+    #[test]
+    fn keeps_prose_as_prose() {
+        assert_eq!(classify(""), ContentSubtype::Prose);
+        assert_eq!(
+            classify("Let me know where you import the photos from."),
+            ContentSubtype::Prose
+        );
+        assert_eq!(
+            classify("Meeting notes\nWe agreed on the plan.\nShip it on Monday."),
+            ContentSubtype::Prose
+        );
+    }
+
+    #[test]
+    fn parses_subtypes_back() {
+        for subtype in [
+            ContentSubtype::Url,
+            ContentSubtype::Color,
+            ContentSubtype::Code(Some("Go".to_string())),
+            ContentSubtype::Phone,
+            ContentSubtype::Prose,
+        ] {
+            assert_eq!(
+                ContentSubtype::parse(subtype.as_str(), subtype.language().map(str::to_string)),
+                Some(subtype)
+            );
+        }
+        assert_eq!(
+            ContentSubtype::parse("url", None),
+            Some(ContentSubtype::Url)
+        );
+        assert_eq!(ContentSubtype::parse("HOLOGRAM", None), None);
+    }
+}
@@ -1,46 +1,53 @@
-use arboard::{Clipboard, ImageData};
-use base64::engine::general_purpose;
-use base64::prelude::*;
-use chrono::{NaiveDateTime, TimeZone, Utc};
+use arboard::Clipboard;
+use chrono::{DateTime, Utc};
 use clipboard_master::{CallbackResult, ClipboardHandler, Master};
-use image::{ImageBuffer, Rgba};
 use once_cell::sync::Lazy;
-use rusqlite::types::{Type, ValueRef};
-use rusqlite::{params, Connection, Row};
-use std::env::current_exe;
-use std::io::Cursor;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Mutex, MutexGuard};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 
-use crate::backend::macos::{current_focus_app_icon_path, current_focus_app_name};
-
-// A simple boolean lock that designed for loop prevention
-// Caller should use this lock proactively notify the `ClipboardHandler` that subsequent clipboard changes will originate internally.
-pub static IS_INTERNAL_PASTE: AtomicBool = AtomicBool::new(false);
-
-const DB_PATH: &str = "clipboard.db";
-static DB_CONN: Lazy<Mutex<Connection>> = Lazy::new(|| {
-    let exe_path = current_exe().unwrap();
-    let exe_parent = exe_path.parent().unwrap();
-    let db_path = exe_parent.join(DB_PATH);
-    let conn = Connection::open(db_path).unwrap();
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS history (
-                id INTEGER PRIMARY KEY,
-                source_app TEXT NOT NULL,
-                icon_path TEXT NOT NULL,
-                content_type TEXT NOT NULL,
-                content BLOB NOT NULL,
-                timestamp TEXT NOT NULL DEFAULT (DATETIME('NOW', 'UTC'))
-    )",
-        [],
-    )
-    .expect("Failed to create table");
-
-    Mutex::new(conn)
-});
+use crate::backend::app_rules::{self, AppAction, AppRule};
+use crate::backend::classifier::ContentSubtype;
+use crate::backend::error::Result;
+use crate::backend::macos::{
+    current_focus_app_bundle_id, current_focus_app_icon_path, current_focus_app_name,
+    file_icon_path, pasteboard_data, pasteboard_types, write_pasteboard,
+};
+use crate::backend::secrets::SecretScanner;
+use crate::backend::store::HistoryStore;
+use crate::backend::utils::{img_data_to_png, sha256_hex};
+
+// Loop prevention: what `write_representations` last wrote, so the `ClipboardHandler` skips the change it triggers
+// A change is only taken for ours if its contents match, and within this window, so a lost event cannot swallow a real copy
+const INTERNAL_WRITE_WINDOW: Duration = Duration::from_secs(2);
+static INTERNAL_WRITE: Lazy<Mutex<Option<InternalWrite>>> = Lazy::new(|| Mutex::new(None));
+
+// Pasteboard types `arboard` does not expose, read from the macOS pasteboard directly
+const RTF_PASTEBOARD_TYPE: &str = "public.rtf";
+const URL_PASTEBOARD_TYPE: &str = "public.url";
+// Markers password managers put on secrets, see http://nspasteboard.org
+// Clips carrying any of them are handled by `concealed_clips`, whichever app is focused
+// Only the macOS pasteboard is read, the markers of other systems (e.g. KDE's `x-kde-passwordManagerHint`) never show up
+const CONCEALED_PASTEBOARD_TYPES: &[&str] = &[
+    "org.nspasteboard.ConcealedType",
+    "org.nspasteboard.TransientType",
+];
+static CONCEALED_CLIPS: Lazy<RwLock<AppAction>> = Lazy::new(|| RwLock::new(AppAction::Ignore));
+// The source of clips copied from anonymized applications, like an application without a name
+const UNKNOWN_APP: &str = "Unknown";
+
+// How often `run_retention_timer` enforces the retention policy
+const RETENTION_INTERVAL: Duration = Duration::from_secs(10 * 60);
+static RETENTION_POLICY: Lazy<RwLock<RetentionPolicy>> =
+    Lazy::new(|| RwLock::new(RetentionPolicy::default()));
+static CAPTURE_STATE: Lazy<RwLock<CaptureState>> =
+    Lazy::new(|| RwLock::new(CaptureState::Recording));
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Item {
@@ -48,41 +55,430 @@ pub struct Item {
     pub source_app: String,
     pub icon_path: String,
     pub content_type: ContentTypes,
+    /// The text, or the Base64 encoded PNG thumbnail of an image.
     pub content: String,
+    /// The sanitized HTML of an `Html` item, for a rich preview, see `utils::sanitize_html`.
+    pub html: Option<String>,
+    /// The files of a `Files` item.
+    pub files: Vec<FileEntry>,
+    /// What the text of a `Text` or `Html` item holds, detected when it was copied.
+    pub subtype: Option<ContentSubtype>,
+    /// When the item was last copied or pasted, the history is ordered by it.
     pub timestamp: chrono::DateTime<Utc>,
+    /// When the content was first copied.
+    pub created_at: chrono::DateTime<Utc>,
+    /// When the content was last copied.
+    pub last_copied_at: chrono::DateTime<Utc>,
+    /// When the item was last pasted from the history, `None` if it never was.
+    pub last_pasted_at: Option<chrono::DateTime<Utc>>,
+    /// How many times the content has been copied, at least once.
+    pub copy_count: i64,
+    /// How many times the item has been pasted from the history.
+    pub paste_count: i64,
+    /// The kinds of secrets found in the content when it was copied, see `secrets::SecretScanner`.
+    pub secrets: Vec<String>,
+    /// Pinned items are listed first and never removed by the retention policy.
+    pub pinned: bool,
 }
 
+/// Position of a record in the history order (newest first).
+///
+/// Pass the cursor of the last record of a page to `HistoryStore::get_records_page` to fetch the next page.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cursor {
+    pub(crate) timestamp: String,
+    pub(crate) id: i64,
+}
+
+impl From<&Item> for Cursor {
+    fn from(item: &Item) -> Self {
+        Cursor {
+            timestamp: item.timestamp.format("%Y-%m-%d %H:%M:%S").to_string(),
+            id: item.id,
+        }
+    }
+}
+
+/// A named collection of history records.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pinboard {
+    pub id: i64,
+    pub name: String,
+}
+
+/// The primary kind of a clip, which of its representations is listed and searched.
 #[derive(Clone, Debug, PartialEq)]
 pub enum ContentTypes {
     Text,
+    /// Rich text, the `content` of the item is its plain text.
+    Html,
     Image,
+    /// Copied files, the `content` of the item is their paths, one per line.
+    Files,
+}
+
+impl ContentTypes {
+    /// The `content_type` column of the `history` table.
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            ContentTypes::Text => "TEXT",
+            ContentTypes::Html => "HTML",
+            ContentTypes::Image => "IMAGE",
+            ContentTypes::Files => "FILES",
+        }
+    }
+
+    /// Inverse of `as_str`, case insensitive, e.g. `image` is `Image`.
+    pub(crate) fn parse(name: &str) -> Option<Self> {
+        [
+            ContentTypes::Text,
+            ContentTypes::Html,
+            ContentTypes::Image,
+            ContentTypes::Files,
+        ]
+        .into_iter()
+        .find(|content_type| content_type.as_str().eq_ignore_ascii_case(name))
+    }
+}
+
+/// A copied file, with its metadata as of when it was copied.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FileEntry {
+    /// Serialized as a string, or as its raw bytes if it is not valid UTF-8.
+    #[serde(
+        serialize_with = "serialize_path",
+        deserialize_with = "deserialize_path"
+    )]
+    pub path: PathBuf,
+    /// Size in bytes, `None` for directories and files missing when copied.
+    pub size: Option<u64>,
+    pub is_dir: bool,
+    /// Whether the file still exists, it may have been moved or deleted since it was copied.
+    ///
+    /// Checked when the UI loads the record, see `refresh_files`.
+    #[serde(skip)]
+    pub exists: bool,
+    /// The icon of the file as shown by Finder, `None` if it cannot be found, see `macos::file_icon_path`.
+    #[serde(skip)]
+    pub icon_path: Option<PathBuf>,
+}
+
+impl FileEntry {
+    /// Reads the metadata of a file.
+    pub fn new(path: &Path) -> Self {
+        let metadata = fs::metadata(path).ok();
+
+        FileEntry {
+            path: path.to_path_buf(),
+            size: metadata
+                .as_ref()
+                .filter(|metadata| metadata.is_file())
+                .map(|metadata| metadata.len()),
+            is_dir: metadata.as_ref().is_some_and(|metadata| metadata.is_dir()),
+            exists: metadata.is_some(),
+            icon_path: None,
+        }
+    }
+
+    /// Checks whether the file still exists and looks up its icon, once per load instead of once per render.
+    fn refresh(&mut self) {
+        self.exists = self.path.exists();
+        self.icon_path = file_icon_path(&self.path, self.is_dir)
+            .inspect_err(|err| {
+                log::warn!("Failed to get the icon of {}: {}", self.path.display(), err)
+            })
+            .ok();
+    }
+
+    /// The file name, as displayed.
+    pub fn name(&self) -> String {
+        self.path
+            .file_name()
+            .unwrap_or(self.path.as_os_str())
+            .to_string_lossy()
+            .to_string()
+    }
+}
+
+/// One of the formats a clip has been copied in.
+///
+/// Applications usually offer several formats at once, e.g. a browser offers HTML and plain text.
+/// Every representation is stored, and pasting a clip offers them all again.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Representation {
+    Text(String),
+    Html(String),
+    Rtf(Vec<u8>),
+    /// The PNG encoded image.
+    Image(Vec<u8>),
+    FileList(Vec<FileEntry>),
+    Url(String),
+}
+
+impl Representation {
+    /// The `kind` column of the `representations` table.
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            Representation::Text(_) => "TEXT",
+            Representation::Html(_) => "HTML",
+            Representation::Rtf(_) => "RTF",
+            Representation::Image(_) => "IMAGE",
+            Representation::FileList(_) => "FILES",
+            Representation::Url(_) => "URL",
+        }
+    }
+
+    /// The macOS pasteboard type holding this representation, file lists are written as file URLs.
+    fn pasteboard_type(&self) -> Option<&'static str> {
+        match self {
+            Representation::Text(_) => Some("public.utf8-plain-text"),
+            Representation::Html(_) => Some("public.html"),
+            Representation::Rtf(_) => Some(RTF_PASTEBOARD_TYPE),
+            Representation::Image(_) => Some("public.png"),
+            Representation::FileList(_) => None,
+            Representation::Url(_) => Some(URL_PASTEBOARD_TYPE),
+        }
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Representation::Text(text) | Representation::Html(text) | Representation::Url(text) => {
+                text.as_bytes().to_vec()
+            }
+            Representation::Rtf(bytes) | Representation::Image(bytes) => bytes.clone(),
+            Representation::FileList(files) => {
+                serde_json::to_vec(files).expect("File entries are always serializable")
+            }
+        }
+    }
+
+    /// Inverse of `kind` and `to_bytes`, `None` for a kind written by a newer version.
+    pub(crate) fn from_bytes(kind: &str, bytes: Vec<u8>) -> Option<Self> {
+        let text = || String::from_utf8_lossy(&bytes).to_string();
+
+        match kind {
+            "TEXT" => Some(Representation::Text(text())),
+            "HTML" => Some(Representation::Html(text())),
+            "RTF" => Some(Representation::Rtf(bytes)),
+            "IMAGE" => Some(Representation::Image(bytes)),
+            // File lists saved before their metadata was recorded are NUL separated paths
+            "FILES" => Some(Representation::FileList(
+                serde_json::from_slice(&bytes).unwrap_or_else(|_| {
+                    bytes
+                        .split(|byte| *byte == 0)
+                        .map(|path| FileEntry::new(Path::new(OsStr::from_bytes(path))))
+                        .collect()
+                }),
+            )),
+            "URL" => Some(Representation::Url(text())),
+            _ => None,
+        }
+    }
+}
+
+/// Limits applied to the clipboard history, the oldest records are evicted first.
+///
+/// A `None` limit is not enforced.
+/// Pinned records and records on a pinboard are exempt, they neither count towards the limits nor get evicted.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetentionPolicy {
+    /// Records not copied or pasted within this duration are removed.
+    pub max_age: Option<Duration>,
+    /// Maximum number of records kept.
+    pub max_items: Option<usize>,
+    /// Maximum total size of the stored contents, in bytes.
+    pub max_total_bytes: Option<u64>,
+    /// How long deleted records stay in the trash, restorable, before they are deleted for good, zero for not at all.
+    pub trash_period: Duration,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        RetentionPolicy {
+            max_age: Some(Duration::from_secs(90 * 24 * 60 * 60)),
+            max_items: Some(10_000),
+            max_total_bytes: Some(1024 * 1024 * 1024),
+            trash_period: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+/// Whether clipboard changes are saved to the history, see `pause_capture`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CaptureState {
+    Recording,
+    /// Nothing is saved until this time, `None` until resumed, e.g. while copying sensitive material.
+    Paused(Option<DateTime<Utc>>),
+}
+
+/// Clipboard contents written by the application, see `INTERNAL_WRITE`.
+struct InternalWrite {
+    fingerprint: String,
+    written_at: Instant,
+}
+
+/// The application a clip has been copied from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClipSource {
+    /// The name of the application.
+    pub app: String,
+    /// Path to the application's icon.
+    pub icon_path: String,
+}
+
+impl ClipSource {
+    /// The source of the clips of an `Anonymize` application, see `AppAction::Anonymize`.
+    pub fn anonymous() -> Self {
+        ClipSource {
+            app: UNKNOWN_APP.to_string(),
+            icon_path: String::new(),
+        }
+    }
 }
 
 struct Handler {
     clipboard_ctx: Option<Clipboard>,
-    ui_notify_tx: mpsc::UnboundedSender<()>,
+    store: Arc<dyn HistoryStore>,
+    ui_notify_tx: mpsc::UnboundedSender<Result<()>>,
+    app_rules: Vec<AppRule>,
+    secret_scanner: SecretScanner,
 }
 
 impl Handler {
-    fn new(ui_notify_tx: mpsc::UnboundedSender<()>) -> Self {
+    fn new(
+        store: Arc<dyn HistoryStore>,
+        ui_notify_tx: mpsc::UnboundedSender<Result<()>>,
+        app_rules: Vec<AppRule>,
+        secret_scanner: SecretScanner,
+    ) -> Self {
         Handler {
             clipboard_ctx: None,
+            store,
             ui_notify_tx,
+            app_rules,
+            secret_scanner,
         }
     }
 
-    fn get_clipboard(&mut self) -> Option<&mut Clipboard> {
-        if self.clipboard_ctx.is_none() {
-            match Clipboard::new() {
-                Ok(ctx) => self.clipboard_ctx = Some(ctx),
-                Err(err) => {
-                    println!("Failed to get clipboard: {}", err);
-                    return None;
+    fn get_clipboard(&mut self) -> Result<&mut Clipboard> {
+        let ctx = match self.clipboard_ctx.take() {
+            Some(ctx) => ctx,
+            None => Clipboard::new()?,
+        };
+
+        Ok(self.clipboard_ctx.insert(ctx))
+    }
+
+    /// Saves every representation of the clipboard contents to the store,
+    /// then enforces the retention policy.
+    ///
+    /// Secrets are looked for first, see `SecretScanner::screen`, a dropped clip is not saved.
+    ///
+    /// # Arguments
+    ///
+    /// * `source_app` - The name of the application the contents have been copied from.
+    /// * `actions` - The capture rule of the application, see `app_rules::load_app_rules`,
+    ///   and the action for concealed contents, see `set_concealed_clips`, if any.
+    ///   Any `Anonymize` anonymizes the clip, the shortest `ExpireAfter` applies.
+    fn save_clipboard(&mut self, source_app: String, actions: &[AppAction]) -> Result<()> {
+        let representations = read_representations(self.get_clipboard()?)?;
+        let screening = self.secret_scanner.screen(&representations);
+        let Some(representations) = screening.representations else {
+            log::info!(
+                "Dropped clipboard contents holding a secret: {}",
+                screening.detections.join(", ")
+            );
+            return Ok(());
+        };
+        let source = if actions.contains(&AppAction::Anonymize) {
+            ClipSource::anonymous()
+        } else {
+            // A missing icon is only cosmetic, the clip is saved regardless
+            let icon_path = current_focus_app_icon_path()
+                .map(|path| path.to_string_lossy().to_string())
+                .unwrap_or_else(|err| {
+                    log::error!("Failed to get the icon of {}: {}", source_app, err);
+                    String::new()
+                });
+            ClipSource {
+                app: source_app,
+                icon_path,
+            }
+        };
+
+        let id = self
+            .store
+            .save_clip(&source, &representations, &screening.detections)?;
+
+        // The shortest of the app rule, concealed contents and secret TTLs applies
+        let expires_after = actions
+            .iter()
+            .filter_map(AppAction::expires_after)
+            .chain(screening.expires_after)
+            .min();
+        if let Some((id, expires_after)) = id.zip(expires_after) {
+            self.store.set_expiry(id, Utc::now() + expires_after)?;
+            self.expire_later(expires_after);
+        }
+
+        self.store.enforce_retention(&retention_policy())?;
+
+        Ok(())
+    }
+
+    /// Whether the clipboard contents are the ones last written by `write_representations`, within `INTERNAL_WRITE_WINDOW`.
+    ///
+    /// Only the first matching change is skipped, the same contents copied again afterwards are saved.
+    fn is_internal_write(&mut self) -> bool {
+        let fingerprint = {
+            let mut internal_write = INTERNAL_WRITE.lock().unwrap();
+
+            match internal_write.as_ref() {
+                Some(write) if write.written_at.elapsed() <= INTERNAL_WRITE_WINDOW => {
+                    write.fingerprint.clone()
+                }
+                Some(_) => {
+                    log::warn!("The clipboard change of an internal paste never came");
+                    *internal_write = None;
+                    return false;
                 }
+                None => return false,
             }
+        };
+
+        // Unreadable contents are not ours to skip, saving them reports the failure
+        let is_internal = self
+            .get_clipboard()
+            .and_then(read_representations)
+            .is_ok_and(|representations| contents_fingerprint(&representations) == fingerprint);
+
+        if is_internal {
+            *INTERNAL_WRITE.lock().unwrap() = None;
         }
 
-        self.clipboard_ctx.as_mut()
+        is_internal
+    }
+
+    /// Enforces the retention policy once an expiring clip is due, the retention timer is too coarse for it.
+    fn expire_later(&self, expires_after: Duration) {
+        let store = self.store.clone();
+        let tx = self.ui_notify_tx.clone();
+
+        thread::spawn(move || {
+            thread::sleep(expires_after);
+
+            match store.enforce_retention(&retention_policy()) {
+                Ok(0) => {}
+                Ok(removed) => {
+                    log::info!("Removed {} expired records", removed);
+                    let _ = tx.send(Ok(()));
+                }
+                Err(err) => {
+                    log::error!("Failed to remove expired records: {}", err);
+                    let _ = tx.send(Err(err));
+                }
+            }
+        });
     }
 }
 
@@ -91,305 +487,392 @@ impl ClipboardHandler for Handler {
     ///
     /// # Processing Logic
     /// 1. Loop Prevention
-    ///    Checks if the change is an internal paste action (`IS_INTERNAL_PASTE`).
-    ///    If so, do not save anything to the database.
-    /// 2. Sensitive Data Filtering
-    ///    Checks if the currently focused application is a password manager.
-    ///    If so, do not save anything to the database.
-    /// 3. Persistence
-    ///    Save the clipboard contents to the SQLite database.
+    ///    Checks if the change is an internal paste action, the contents written by `write_representations`.
+    ///    If so, do not save anything to the store.
+    /// 2. Paused Capture
+    ///    Checks if capture is paused, see `pause_capture`.
+    ///    If so, do not save anything to the store.
+    /// 3. Concealed Contents
+    ///    Checks if the clipboard contents are marked as a secret or as transient, e.g. by a password manager.
+    ///    If so, do not save anything to the store, or save them anonymized or expiring, see `set_concealed_clips`.
+    /// 4. Capture Rules
+    ///    Applies the first capture rule matching the currently focused application, see `app_rules`.
+    ///    Clips copied from ignored applications, e.g. password managers, are not saved to the store.
+    /// 5. Persistence
+    ///    Save every representation of the clipboard contents to the store,
+    ///    then enforce the retention policy.
     fn on_clipboard_change(&mut self) -> CallbackResult {
         // If the clipboard changed event is triggered by our own action
-        // DO NOT save anything to the database.
+        // DO NOT save anything to the store.
         // Because the event is triggered due to user selected a clipboard item in our Dioxus App.
-        if IS_INTERNAL_PASTE.swap(false, Ordering::Relaxed) {
+        if self.is_internal_write() {
             return CallbackResult::Next;
         }
 
-        // If the clipboard changed event is triggered from a password manager app
-        // DO NOT save anything to the database
-        const IGNORED_APPS: &[&str] = &["Passwords", "Keychain Access", "Bitwarden"];
-        let current_focus_app = current_focus_app_name();
+        // If capture is paused
+        // DO NOT save anything to the store
+        if capture_state() != CaptureState::Recording {
+            log::trace!("Capture is paused, skipped clipboard contents");
+            return CallbackResult::Next;
+        }
 
-        if IGNORED_APPS
-            .iter()
-            .any(|ignored_app| current_focus_app.contains(ignored_app))
-        {
+        // If the clipboard contents are marked as concealed or transient
+        // DO NOT save anything to the store, unless set to keep them for a while
+        let concealed_action = pasteboard_types()
+            .into_iter()
+            .find(|pasteboard_type| CONCEALED_PASTEBOARD_TYPES.contains(&pasteboard_type.as_str()))
+            .map(|marker| {
+                let action = concealed_clips();
+                log::info!("Clipboard contents marked as {}: {:?}", marker, action);
+                action
+            });
+
+        if concealed_action == Some(AppAction::Ignore) {
             return CallbackResult::Next;
         }
 
-        // Save the clipboard contents to the SQLite database
-        if let Some(clipboard) = self.get_clipboard() {
-            if let Ok(text) = clipboard.get_text() {
-                save_text(&text).unwrap();
-            } else if let Ok(image) = clipboard.get_image() {
-                save_image(&image).unwrap();
-            }
+        // If the clipboard changed event is triggered from an ignored app, e.g. a password manager
+        // DO NOT save anything to the store
+        let current_focus_app = current_focus_app_name();
+        let action = app_rules::action_for(
+            &self.app_rules,
+            current_focus_app_bundle_id().as_deref(),
+            &current_focus_app,
+        )
+        .cloned();
+
+        if action == Some(AppAction::Ignore) {
+            return CallbackResult::Next;
         }
 
-        // Notify the item has been saved to the database
-        self.ui_notify_tx.send(()).unwrap();
+        // Save the clipboard contents to the store
+        let actions = action
+            .into_iter()
+            .chain(concealed_action)
+            .collect::<Vec<_>>();
+        let result = self.save_clipboard(current_focus_app, &actions);
+
+        if let Err(err) = &result {
+            log::error!("Failed to save the clipboard contents: {}", err);
+        }
+
+        // Notify the item has been saved to the store, or why it has not
+        // Nobody is listening anymore once the UI is gone
+        if self.ui_notify_tx.send(result).is_err() {
+            return CallbackResult::Stop;
+        }
 
         CallbackResult::Next
     }
 }
 
 /// Listen to system clipboard changes.
-/// When clipboard changes, save the latest item to the SQLite database
+/// When clipboard changes, save the latest item to the store, following the capture rules of `app-rules.json`
+///
+/// Unreadable capture rules are reported on `tx`, the default rules apply instead.
 ///
 /// # Arguments
 ///
-/// * `tx` - The channel to notify the item has been saved to the database
-/// 
+/// * `store` - The store to save the clipboard items to
+/// * `tx` - The channel to notify the item has been saved to the store, or the error saving it
+///
+/// Fails if the system clipboard cannot be watched.
+///
 /// Example:
 /// ```
 /// use crate::backend::clipboard;
+/// use crate::backend::store::SqliteStore;
 ///
-/// let (tx, mut rx) = mpsc::unbounded_channel::<()>();
-/// clipboard::listen(tx); // Start listening
+/// let store = Arc::new(SqliteStore::open_default()?);
+/// let (tx, mut rx) = mpsc::unbounded_channel::<Result<()>>();
+/// clipboard::listen(store, tx)?; // Start listening
 /// ```
-pub fn listen(tx: mpsc::UnboundedSender<()>) {
-    let handler = Handler::new(tx);
-    Master::new(handler).unwrap().run().unwrap();
+pub fn listen(store: Arc<dyn HistoryStore>, tx: mpsc::UnboundedSender<Result<()>>) -> Result<()> {
+    let app_rules = app_rules::load_app_rules().unwrap_or_else(|err| {
+        log::error!(
+            "Failed to load the capture rules, using the defaults: {}",
+            err
+        );
+        let _ = tx.send(Err(err));
+        app_rules::default_app_rules()
+    });
+    let handler = Handler::new(store, tx, app_rules, SecretScanner::default());
+    Master::new(handler)?.run()?;
+
+    Ok(())
 }
 
-/// Get all of the records from the SQLite database
+/// Replaces the system clipboard contents, offering every representation at once.
+///
+/// The clipboard listener skips the change it triggers, the contents are not saved again as a new copy.
+///
+/// Fails if the system clipboard rejected the contents.
 ///
-/// # Example:
+/// # Example
 /// ```
 /// use crate::backend::clipboard;
 ///
-/// let records = clipboard::get_all_records();
-/// println!("{:?}", records); // Output: Ok([Item { id: 1, source_app: "Code", icon_path: "/foo/bar/Code.png", content_type: TEXT, content: "Hello", timestamp: 2025-12-27T17:11:28Z }])
-pub fn get_all_records() -> rusqlite::Result<Vec<Item>> {
-    let conn = db_conn();
-
-    let mut stmt = conn.prepare(
-        "SELECT id, source_app, icon_path, content_type, content, timestamp
-         FROM history
-         ORDER BY timestamp DESC",
-    )?;
-
-    let history_iter = stmt.query_map(params![], row_to_item)?;
+/// clipboard::write_representations(&store.get_representations(1)?)?;
+/// ```
+pub fn write_representations(representations: &[Representation]) -> Result<()> {
+    let flavors = representations
+        .iter()
+        .filter_map(|representation| {
+            representation
+                .pasteboard_type()
+                .map(|pasteboard_type| (pasteboard_type, representation.to_bytes()))
+        })
+        .collect::<Vec<_>>();
+    let files = representations
+        .iter()
+        .find_map(|representation| match representation {
+            Representation::FileList(files) => Some(
+                files
+                    .iter()
+                    .map(|file| file.path.as_path())
+                    .collect::<Vec<_>>(),
+            ),
+            _ => None,
+        })
+        .unwrap_or_default();
+
+    // Remembered first, the listener may see the change before the write returns
+    *INTERNAL_WRITE.lock().unwrap() = Some(InternalWrite {
+        fingerprint: contents_fingerprint(representations),
+        written_at: Instant::now(),
+    });
+
+    let result = write_pasteboard(&flavors, &files);
+    if result.is_err() {
+        *INTERNAL_WRITE.lock().unwrap() = None;
+    }
 
-    history_iter.collect()
+    result
 }
 
-/// Get the latest records from the SQLite database
+/// Checks whether the copied files of items still exist and looks up their icons, see `FileEntry::exists`.
 ///
-/// # Arguments
+/// Called on the items loaded from the store, outside of it, so file system and AppKit calls never hold its lock.
 ///
-/// * `limit` - The number of records to return
-///
-/// # Example:
+/// # Example
 /// ```
 /// use crate::backend::clipboard;
 ///
-/// let records = clipboard::get_recent_records(1);
-/// println!("{:?}", records); // Output: Ok([Item { id: 1, source_app: "Code", icon_path: "/foo/bar/Code.png", content_type: TEXT, content: "Hello", timestamp: 2025-12-27T17:11:28Z }])
+/// let items = clipboard::refresh_files(store.get_pinned_records()?);
 /// ```
-pub fn get_recent_records(limit: i64) -> rusqlite::Result<Vec<Item>> {
-    let conn = db_conn();
+pub fn refresh_files(mut items: Vec<Item>) -> Vec<Item> {
+    items
+        .iter_mut()
+        .flat_map(|item| item.files.iter_mut())
+        .for_each(FileEntry::refresh);
+    items
+}
 
-    let mut stmt = conn.prepare(
-        "SELECT id, source_app, icon_path, content_type, content, timestamp
-         FROM history
-         ORDER BY timestamp DESC
-         LIMIT ?1",
-    )?;
+/// Replaces the retention policy, the new limits are enforced immediately.
+///
+/// # Example
+/// ```
+/// use crate::backend::clipboard::{self, RetentionPolicy};
+///
+/// clipboard::set_retention_policy(&*store, RetentionPolicy {
+///     max_items: Some(500),
+///     ..Default::default()
+/// });
+/// ```
+pub fn set_retention_policy(store: &dyn HistoryStore, policy: RetentionPolicy) -> Result<usize> {
+    *RETENTION_POLICY.write().unwrap() = policy;
 
-    let history_iter = stmt.query_map(params![limit], row_to_item)?;
+    store.enforce_retention(&retention_policy())
+}
 
-    history_iter.collect()
+/// Returns the current retention policy.
+pub fn retention_policy() -> RetentionPolicy {
+    RETENTION_POLICY.read().unwrap().clone()
 }
 
-/// Search for specific text in the SQLite database
-///
-/// # Arguments
+/// Replaces what happens to the clipboard contents marked as concealed or transient, e.g. by a password manager.
 ///
-/// * `term` - The text to search for
+/// `Ignore` by default, nothing is saved, `ExpireAfter` keeps them for a while, e.g. to paste a password twice.
 ///
-/// # Example:
+/// # Example
 /// ```
+/// use crate::backend::app_rules::AppAction;
 /// use crate::backend::clipboard;
 ///
-/// let records = clipboard::search_text("Hello World");
-/// println!("{:?}", records); // Output: Ok([Item { id: 1, source_app: "Code", icon_path: "/foo/bar/Code.png", content_type: TEXT, content: "Hello World", timestamp: 2025-12-27T17:28:01Z }])
+/// clipboard::set_concealed_clips(AppAction::ExpireAfter(30));
 /// ```
-pub fn search_text(term: &str) -> rusqlite::Result<Vec<Item>> {
-    let conn = db_conn();
-    let pattern = format!("%{}%", term);
-
-    let mut stmt = conn.prepare(
-        "SELECT id, source_app, icon_path, content_type, content, timestamp
-         FROM history
-         WHERE content_type = 'TEXT' AND content LIKE ?1
-         ORDER BY timestamp DESC
-        ",
-    )?;
-
-    let history_iter = stmt.query_map(params![pattern], row_to_item)?;
+pub fn set_concealed_clips(action: AppAction) {
+    *CONCEALED_CLIPS.write().unwrap() = action;
+}
 
-    history_iter.collect()
+/// Returns what happens to the clipboard contents marked as concealed or transient.
+pub fn concealed_clips() -> AppAction {
+    CONCEALED_CLIPS.read().unwrap().clone()
 }
 
-/// Updates the timestamp of a specific history record.
-///
-/// This function sets the `timestamp` column of the item with the given `id`
-/// to the **current UTC time** in the `history` database table.
-///
-/// # Purpose
-/// This is typically used when a user re-pastes an old clipboard item.
-/// By updating the timestamp, the item is effectively "bumped" to the
-/// **top of the list** (marked as most recently used).
+/// Stops saving clipboard changes for a while, or until `resume_capture`.
 ///
 /// # Arguments
 ///
-/// * `id` - The unique identifier (Primary Key) of the history record.
+/// * `duration` - How long capture is paused, `None` until resumed.
 ///
 /// # Example
 /// ```
 /// use crate::backend::clipboard;
 ///
-/// clipboard::update_timestamp(1);
+/// clipboard::pause_capture(Some(Duration::from_secs(60))); // Copy a secret in peace
 /// ```
-pub fn update_timestamp(id: i64) -> rusqlite::Result<()> {
-    let conn = db_conn();
+pub fn pause_capture(duration: Option<Duration>) {
+    let until = duration.map(|duration| Utc::now() + duration);
+    *CAPTURE_STATE.write().unwrap() = CaptureState::Paused(until);
 
-    conn.execute(
-        "UPDATE history SET timestamp = DATETIME('NOW', 'UTC') WHERE id = ?1",
-        params![id],
-    )?;
+    log::info!("Paused capture until {:?}", until);
+}
 
-    Ok(())
+/// Saves clipboard changes again, see `pause_capture`.
+pub fn resume_capture() {
+    *CAPTURE_STATE.write().unwrap() = CaptureState::Recording;
+
+    log::info!("Resumed capture");
 }
 
-/// Saves text content to the clipboard history database.
-///
-/// It automatically captures context metadata:
-/// - `source_app`: Name of the focused application.
-/// - `icon_path`: Path to the application's icon.
-///
-/// # Arguments
-///
-/// * `content` - The text string to be saved.
-fn save_text(content: &str) -> rusqlite::Result<()> {
-    let conn = db_conn();
-    let source_app = current_focus_app_name();
-    let icon_path = current_focus_app_icon_path().to_string_lossy().to_string();
-
-    let row_effected = conn.execute(
-        "
-        UPDATE history
-        SET timestamp = DATETIME('NOW', 'UTC'), source_app = ?1, icon_path = ?2
-        WHERE content_type = 'TEXT' AND content = ?3
-    ",
-        params![source_app, icon_path, content],
-    )?;
-
-    if row_effected == 0 {
-        conn.execute(
-            "INSERT INTO history (source_app, icon_path, content_type, content) VALUES (?1, ?2, 'TEXT', ?3)",
-            params![source_app, icon_path, content],
-        )?;
+/// Returns whether clipboard changes are saved, a timed pause is over once its time has passed.
+pub fn capture_state() -> CaptureState {
+    match *CAPTURE_STATE.read().unwrap() {
+        CaptureState::Paused(Some(until)) if until <= Utc::now() => CaptureState::Recording,
+        state => state,
     }
-
-    Ok(())
 }
 
-/// Saves image content to the clipboard history database.
-///
-/// Similar to `save_text` function.
+/// Enforces the retention policy periodically, blocking the current thread.
 ///
 /// # Arguments
 ///
-/// * `content` - The raw image data captured from the system clipboard.
-fn save_image(content: &ImageData) -> rusqlite::Result<()> {
-    let conn = db_conn();
-    let source_app = current_focus_app_name();
-    let icon_path = current_focus_app_icon_path().to_string_lossy().to_string();
-    let content_bytes = content.bytes.as_ref();
-    let width = content.width as u32;
-    let height = content.height as u32;
-    let png_bytes = if let Some(img_buffer) =
-        ImageBuffer::<Rgba<u8>, _>::from_raw(width, height, content_bytes)
-    {
-        let mut bytes: Vec<u8> = Vec::new();
-
-        if img_buffer
-            .write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
-            .is_ok()
-        {
-            bytes
-        } else {
-            Vec::new()
+/// * `store` - The store to evict the records from
+/// * `tx` - The channel to notify that records have been removed from the store, or the error removing them
+///
+/// Example:
+/// ```
+/// use crate::backend::clipboard;
+///
+/// let (tx, mut rx) = mpsc::unbounded_channel::<Result<()>>();
+/// thread::spawn(move || clipboard::run_retention_timer(store, tx));
+/// ```
+pub fn run_retention_timer(store: Arc<dyn HistoryStore>, tx: mpsc::UnboundedSender<Result<()>>) {
+    loop {
+        let result = match store.enforce_retention(&retention_policy()) {
+            Ok(0) => None,
+            Ok(removed) => {
+                log::info!("Retention removed {} records", removed);
+                Some(Ok(()))
+            }
+            Err(err) => {
+                log::error!("Failed to enforce retention: {}", err);
+                Some(Err(err))
+            }
+        };
+
+        if result.is_some_and(|result| tx.send(result).is_err()) {
+            return;
         }
-    } else {
-        Vec::new()
-    };
-
-    let rows_affected = conn.execute(
-        "UPDATE history 
-         SET timestamp = DATETIME('NOW', 'UTC'), source_app = ?1, icon_path = ?2
-         WHERE content_type = 'IMAGE' AND content = ?3",
-        params![source_app, icon_path, png_bytes],
-    )?;
-
-    if rows_affected == 0 {
-        conn.execute(
-            "INSERT INTO history (source_app, icon_path, content_type, content) VALUES (?1, ?2, 'IMAGE', ?3)",
-            params![source_app, icon_path, png_bytes],
-        )?;
-    }
 
-    Ok(())
+        thread::sleep(RETENTION_INTERVAL);
+    }
 }
 
-/// Maps a raw database row to the `Item` struct.
+/// Reads every representation of the system clipboard contents.
 ///
-/// # Arguments
+/// Text, HTML, images and file lists are read through `arboard`,
+/// RTF and URLs straight from the macOS pasteboard.
+/// Formats the clipboard does not hold are skipped, fails if an image cannot be converted.
+fn read_representations(clipboard: &mut Clipboard) -> Result<Vec<Representation>> {
+    let mut representations = Vec::new();
+
+    if let Ok(text) = clipboard.get_text() {
+        representations.push(Representation::Text(text));
+    }
+    if let Ok(html) = clipboard.get().html() {
+        representations.push(Representation::Html(html));
+    }
+    if let Some(rtf) = pasteboard_data(RTF_PASTEBOARD_TYPE) {
+        representations.push(Representation::Rtf(rtf));
+    }
+    if let Ok(image) = clipboard.get_image() {
+        representations.push(Representation::Image(img_data_to_png(&image)?));
+    }
+    if let Ok(paths) = clipboard.get().file_list() {
+        representations.push(Representation::FileList(
+            paths.iter().map(|path| FileEntry::new(path)).collect(),
+        ));
+    }
+    if let Some(url) = pasteboard_data(URL_PASTEBOARD_TYPE) {
+        representations.push(Representation::Url(
+            String::from_utf8_lossy(&url).to_string(),
+        ));
+    }
+
+    Ok(representations)
+}
+
+/// Identifies clipboard contents, the same for the contents written and the contents read back.
 ///
-/// * `row` - A reference to a single row result from a rusqlite query.
-fn row_to_item(row: &Row) -> rusqlite::Result<Item> {
-    let id: i64 = row.get(0)?;
-    let source_app: String = row.get(1)?;
-    let icon_path: String = row.get(2)?;
-    let content_type: String = row.get(3)?;
-    let content: ValueRef = row.get_ref(4)?;
-    let timestamp: String = row.get(5)?;
-
-    let content_type = match content_type.as_str() {
-        "IMAGE" => ContentTypes::Image,
-        "TEXT" => ContentTypes::Text,
-        _ => unreachable!(),
-    };
-
-    let content_raw_bytes: Vec<u8> = match content.data_type() {
-        Type::Blob => content.as_blob()?.to_vec(),
-        Type::Text => content.as_str()?.as_bytes().to_vec(),
-        _ => Vec::new(),
-    };
-
-    let content = match content_type {
-        ContentTypes::Image => general_purpose::STANDARD.encode(&content_raw_bytes),
-        ContentTypes::Text => String::from_utf8_lossy(&content_raw_bytes).to_string(),
-    };
-
-    let timestamp = NaiveDateTime::parse_from_str(&timestamp, "%Y-%m-%d %H:%M:%S")
-        .map(|naive| Utc.from_utc_datetime(&naive))
-        .unwrap_or_else(|_| Utc::now());
-
-    Ok(Item {
-        id,
-        source_app,
-        icon_path,
-        content_type,
-        content,
-        timestamp,
-    })
+/// Only the first of the file list, image, text, HTML, URL and RTF is used, the system may add other representations.
+/// Images are identified by their decoded RGBA pixels, the system may re-encode them in another format.
+fn contents_fingerprint(representations: &[Representation]) -> String {
+    let bytes = ["FILES", "IMAGE", "TEXT", "HTML", "URL", "RTF"]
+        .into_iter()
+        .find_map(|kind| {
+            representations
+                .iter()
+                .find(|representation| representation.kind() == kind)
+        })
+        .map(|representation| match representation {
+            Representation::Image(png_bytes) => image::load_from_memory(png_bytes)
+                .map(|image| {
+                    let pixels = image.to_rgba8();
+                    [pixels.width().to_be_bytes(), pixels.height().to_be_bytes()]
+                        .concat()
+                        .into_iter()
+                        .chain(pixels.into_raw())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            Representation::FileList(files) => files
+                .iter()
+                .map(|file| file.path.as_os_str().as_bytes())
+                .collect::<Vec<_>>()
+                .join(&b'\n'),
+            representation => representation.to_bytes(),
+        })
+        .unwrap_or_default();
+
+    sha256_hex(&bytes)
 }
 
-/// Get a connection to the SQLite database
-fn db_conn() -> MutexGuard<'static, Connection> {
-    DB_CONN.lock().unwrap()
+/// Serializes a path as a string, or as its raw bytes if it is not valid UTF-8, see `FileEntry::path`.
+fn serialize_path<S: Serializer>(
+    path: &Path,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    match path.to_str() {
+        Some(path) => serializer.serialize_str(path),
+        None => serializer.collect_seq(path.as_os_str().as_bytes()),
+    }
+}
+
+/// Inverse of `serialize_path`.
+fn deserialize_path<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<PathBuf, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum SerializedPath {
+        Text(String),
+        Bytes(Vec<u8>),
+    }
+
+    Ok(match SerializedPath::deserialize(deserializer)? {
+        SerializedPath::Text(path) => PathBuf::from(path),
+        SerializedPath::Bytes(bytes) => PathBuf::from(OsString::from_vec(bytes)),
+    })
 }
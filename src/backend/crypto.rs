@@ -0,0 +1,395 @@
+use once_cell::sync::OnceCell;
+use ring::aead::{self, Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use rusqlite::functions::FunctionFlags;
+use rusqlite::types::ValueRef;
+use rusqlite::Connection;
+#[cfg(target_os = "macos")]
+use security_framework::passwords;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::backend::error::{Error, Result};
+use crate::backend::paths;
+
+/// Environment variable pointing at a key file, takes precedence over any other key store.
+pub const KEY_FILE_ENV: &str = "PASTE_FORK_KEY_FILE";
+
+const KEY_FILE_NAME: &str = "clipboard.key";
+const KEY_LEN: usize = 32;
+// Prefix of every ciphertext, identifies the format for future key or cipher rotation
+const FORMAT_VERSION: u8 = 1;
+
+static CIPHER: OnceCell<Cipher> = OnceCell::new();
+
+/// Authenticated encryption of the clipboard contents at rest.
+///
+/// Contents are sealed with ChaCha20-Poly1305 under a random nonce, so equal contents never
+/// produce equal ciphertexts. Deduplication relies on `fingerprint` instead.
+pub struct Cipher {
+    key: LessSafeKey,
+    fingerprint_key: hmac::Key,
+    rng: SystemRandom,
+}
+
+#[derive(Debug)]
+pub struct DecryptError;
+
+impl fmt::Display for DecryptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to decrypt: wrong key or corrupted data")
+    }
+}
+
+impl std::error::Error for DecryptError {}
+
+impl Cipher {
+    /// Derives independent encryption and fingerprint keys from the master key.
+    fn new(master_key: &[u8]) -> Result<Self> {
+        let master_key = hmac::Key::new(hmac::HMAC_SHA256, master_key);
+        let encryption_key = hmac::sign(&master_key, b"paste-fork encryption");
+        let fingerprint_key = hmac::sign(&master_key, b"paste-fork fingerprint");
+        let key = UnboundKey::new(&CHACHA20_POLY1305, encryption_key.as_ref())
+            .map_err(|_| Error::Platform("invalid encryption key length".to_string()))?;
+
+        Ok(Cipher {
+            key: LessSafeKey::new(key),
+            fingerprint_key: hmac::Key::new(hmac::HMAC_SHA256, fingerprint_key.as_ref()),
+            rng: SystemRandom::new(),
+        })
+    }
+
+    /// Encrypts `plaintext` into `version || nonce || ciphertext || tag`.
+    ///
+    /// Fails if the system random number generator fails.
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = [0; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| Error::Platform("failed to generate a nonce".to_string()))?;
+
+        let mut in_out = plaintext.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::empty(),
+                &mut in_out,
+            )
+            .map_err(|_| Error::Platform("failed to encrypt".to_string()))?;
+
+        let mut sealed = Vec::with_capacity(1 + NONCE_LEN + in_out.len());
+        sealed.push(FORMAT_VERSION);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&in_out);
+        Ok(sealed)
+    }
+
+    /// Decrypts the output of `encrypt`, failing if it has been tampered with.
+    pub fn decrypt(&self, sealed: &[u8]) -> std::result::Result<Vec<u8>, DecryptError> {
+        let (version, rest) = sealed.split_first().ok_or(DecryptError)?;
+
+        if *version != FORMAT_VERSION || rest.len() < NONCE_LEN + aead::MAX_TAG_LEN {
+            return Err(DecryptError);
+        }
+
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| DecryptError)?;
+        let mut in_out = ciphertext.to_vec();
+        let plaintext_len = self
+            .key
+            .open_in_place(nonce, Aad::empty(), &mut in_out)
+            .map_err(|_| DecryptError)?
+            .len();
+
+        in_out.truncate(plaintext_len);
+        Ok(in_out)
+    }
+
+    /// Returns a keyed hash (HMAC-SHA256, hex encoded) of `plaintext`.
+    ///
+    /// Used to look up equal contents without storing an unkeyed hash,
+    /// which would let anyone holding the database confirm a guessed content.
+    pub fn fingerprint(&self, plaintext: &[u8]) -> String {
+        hmac::sign(&self.fingerprint_key, plaintext)
+            .as_ref()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+/// Load the process wide cipher, see `cipher`.
+///
+/// A new key is only generated if `may_generate`, i.e. nothing has been encrypted yet.
+/// A missing key is an error otherwise: generating one would leave every stored content undecryptable.
+/// Once loaded, later calls return the same cipher.
+///
+/// Fails if the key store cannot be read or written, e.g. the keychain is locked,
+/// the key is loaded again on the next call.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::crypto;
+///
+/// let cipher = crypto::init(true)?;
+/// ```
+pub fn init(may_generate: bool) -> Result<&'static Cipher> {
+    CIPHER.get_or_try_init(|| {
+        let store = key_store()?;
+        let master_key = match store.load() {
+            Ok(Some(key)) => key,
+            Ok(None) if !may_generate => {
+                return Err(Error::Platform(format!(
+                    "no encryption key found in {}, the clipboard database cannot be decrypted without it",
+                    store.name()
+                )))
+            }
+            Ok(None) => {
+                let mut key = vec![0; KEY_LEN];
+                SystemRandom::new().fill(&mut key).map_err(|_| {
+                    Error::Platform("failed to generate an encryption key".to_string())
+                })?;
+                store.store(&key).map_err(|err| {
+                    Error::Platform(format!(
+                        "failed to store the encryption key in {}: {}",
+                        store.name(),
+                        err
+                    ))
+                })?;
+                log::info!("Generated a new encryption key in {}", store.name());
+                key
+            }
+            Err(err) => {
+                return Err(Error::Platform(format!(
+                    "failed to load the encryption key from {}: {}",
+                    store.name(),
+                    err
+                )))
+            }
+        };
+
+        Cipher::new(&master_key)
+    })
+}
+
+/// Return the process wide cipher.
+///
+/// Fails if it has not been loaded yet, see `init`, which `SqliteStore::open` does.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::crypto;
+///
+/// let sealed = crypto::cipher()?.encrypt(b"Hello")?;
+/// assert_eq!(crypto::cipher()?.decrypt(&sealed).unwrap(), b"Hello");
+/// ```
+pub fn cipher() -> Result<&'static Cipher> {
+    CIPHER
+        .get()
+        .ok_or_else(|| Error::Platform("the encryption key has not been loaded".to_string()))
+}
+
+/// Loads a fixed key instead of the key of the user, so tests never read nor write the key store.
+#[cfg(test)]
+pub(crate) fn init_for_tests() -> &'static Cipher {
+    CIPHER.get_or_init(|| Cipher::new(&[7; KEY_LEN]).expect("Valid key length"))
+}
+
+/// Write a copy of the key into a key file, e.g. next to the backups.
+///
+/// Point `PASTE_FORK_KEY_FILE` at the copy to decrypt the backups on another machine.
+/// A copy already holding the key is left as is.
+///
+/// Fails if the key cannot be loaded, or if the file holds another key.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::crypto;
+///
+/// crypto::export_key(Path::new("/Volumes/Backup/paste-fork/clipboard.key"))?;
+/// ```
+pub fn export_key(path: &Path) -> Result<()> {
+    let store = key_store()?;
+    let key = store
+        .load()?
+        .ok_or_else(|| Error::Platform(format!("no encryption key found in {}", store.name())))?;
+    let copy = KeyFile::new(path);
+
+    match copy.load()? {
+        Some(existing) if existing == key => Ok(()),
+        Some(_) => Err(Error::Platform(format!(
+            "{} holds another encryption key",
+            copy.name()
+        ))),
+        None => {
+            copy.store(&key)?;
+            log::info!("Exported the encryption key to {}", copy.name());
+            Ok(())
+        }
+    }
+}
+
+/// Registers the `decrypt_text(content)` SQL function used by the FTS triggers.
+///
+/// Encrypted BLOBs are decrypted to TEXT, any other value is returned as is.
+pub fn register_sql_functions(conn: &Connection) -> rusqlite::Result<()> {
+    conn.create_scalar_function(
+        "decrypt_text",
+        1,
+        FunctionFlags::SQLITE_UTF8
+            | FunctionFlags::SQLITE_DETERMINISTIC
+            | FunctionFlags::SQLITE_INNOCUOUS,
+        |ctx| match ctx.get_raw(0) {
+            ValueRef::Blob(sealed) => {
+                let plaintext = cipher()
+                    .map_err(|err| rusqlite::Error::UserFunctionError(Box::new(err)))?
+                    .decrypt(sealed)
+                    .map_err(|err| rusqlite::Error::UserFunctionError(Box::new(err)))?;
+                Ok(rusqlite::types::Value::Text(
+                    String::from_utf8_lossy(&plaintext).to_string(),
+                ))
+            }
+            value => Ok(rusqlite::types::Value::from(value)),
+        },
+    )
+}
+
+// ------------------------------------------------------------------
+//                            KEY STORES
+// ------------------------------------------------------------------
+/// A place the master key is persisted in.
+pub trait KeyStore {
+    /// Human readable location, for logging.
+    fn name(&self) -> String;
+    /// Returns the stored key, `None` if no key has been stored yet.
+    fn load(&self) -> io::Result<Option<Vec<u8>>>;
+    /// Persists a newly generated key.
+    fn store(&self, key: &[u8]) -> io::Result<()>;
+}
+
+/// Stores the key hex encoded in a file only readable by the current user.
+pub struct KeyFile {
+    path: PathBuf,
+}
+
+impl KeyFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        KeyFile { path: path.into() }
+    }
+}
+
+impl KeyStore for KeyFile {
+    fn name(&self) -> String {
+        format!("key file {:?}", self.path)
+    }
+
+    fn load(&self) -> io::Result<Option<Vec<u8>>> {
+        match fs::read_to_string(&self.path) {
+            Ok(hex) => decode_key(&hex).map(Some),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn store(&self, key: &[u8]) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        options
+            .open(&self.path)?
+            .write_all(encode_key(key).as_bytes())
+    }
+}
+
+/// Stores the key as a generic password in the login keychain, through the Security framework.
+///
+/// The key never leaves the process, unlike with the `security` CLI, which takes it as an argument.
+#[cfg(target_os = "macos")]
+pub struct Keychain;
+
+#[cfg(target_os = "macos")]
+impl Keychain {
+    const SERVICE: &'static str = "paste-fork";
+    const ACCOUNT: &'static str = "clipboard-key";
+    // `errSecItemNotFound`, returned when no item matches
+    const ITEM_NOT_FOUND: i32 = -25300;
+}
+
+#[cfg(target_os = "macos")]
+impl KeyStore for Keychain {
+    fn name(&self) -> String {
+        "login keychain".to_string()
+    }
+
+    fn load(&self) -> io::Result<Option<Vec<u8>>> {
+        match passwords::get_generic_password(Self::SERVICE, Self::ACCOUNT) {
+            Ok(hex) => decode_key(&String::from_utf8_lossy(&hex)).map(Some),
+            Err(err) if err.code() == Self::ITEM_NOT_FOUND => Ok(None),
+            Err(err) => Err(io::Error::other(err)),
+        }
+    }
+
+    fn store(&self, key: &[u8]) -> io::Result<()> {
+        passwords::set_generic_password(Self::SERVICE, Self::ACCOUNT, encode_key(key).as_bytes())
+            .map_err(io::Error::other)
+    }
+}
+
+/// Selects where the master key is kept.
+///
+/// 1. The key file named by `PASTE_FORK_KEY_FILE`.
+/// 2. An existing `clipboard.key` in the data directory.
+/// 3. The login keychain on macOS.
+/// 4. A new `clipboard.key` in the data directory, e.g. on headless Linux.
+fn key_store() -> io::Result<Box<dyn KeyStore>> {
+    if let Some(path) = env::var_os(KEY_FILE_ENV).filter(|path| !path.is_empty()) {
+        return Ok(Box::new(KeyFile::new(path)));
+    }
+
+    let default_key_file = paths::data_dir()?.join(KEY_FILE_NAME);
+
+    #[cfg(target_os = "macos")]
+    if !default_key_file.exists() {
+        return Ok(Box::new(Keychain));
+    }
+
+    Ok(Box::new(KeyFile::new(default_key_file)))
+}
+
+fn encode_key(key: &[u8]) -> String {
+    key.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_key(hex: &str) -> io::Result<Vec<u8>> {
+    let hex = hex.trim();
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed encryption key");
+
+    if hex.len() != KEY_LEN * 2 {
+        return Err(invalid());
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(invalid)
+        })
+        .collect()
+}
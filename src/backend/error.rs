@@ -0,0 +1,121 @@
+use std::fmt;
+use std::io;
+
+use crate::backend::migrations::MigrationError;
+
+/// Errors of the backend, none of them is fatal to the application.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::error::Result;
+///
+/// fn count(store: &dyn HistoryStore) -> Result<i64> {
+///     store.count_records()
+/// }
+/// ```
+#[derive(Debug)]
+pub enum Error {
+    /// The clipboard database failed, e.g. it is locked or a content cannot be decrypted.
+    Db(rusqlite::Error),
+    /// The system clipboard could not be read or written.
+    Clipboard(String),
+    /// An image could not be decoded or encoded.
+    Image(image::ImageError),
+    /// A macOS API or command failed.
+    Platform(String),
+    /// The database was written by a newer version, opening it could corrupt the history, see `migrations::migrate`.
+    SchemaTooNew {
+        found: i64,
+        supported: i64,
+    },
+    /// A file to restore is no intact clipboard database, e.g. it is corrupted.
+    InvalidBackup(String),
+    /// Contents are encrypted with another key than the one in use, e.g. a backup of another machine.
+    WrongKey,
+    Io(io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Db(err) => write!(f, "database error: {}", err),
+            Error::Clipboard(message) => write!(f, "clipboard error: {}", message),
+            Error::Image(err) => write!(f, "image error: {}", err),
+            Error::Platform(message) => write!(f, "system error: {}", message),
+            Error::SchemaTooNew { found, supported } => write!(
+                f,
+                "database schema version {} is newer than the supported version {}",
+                found, supported
+            ),
+            Error::InvalidBackup(message) => write!(f, "invalid backup: {}", message),
+            Error::WrongKey => write!(f, "the contents are encrypted with another key"),
+            Error::Io(err) => write!(f, "I/O error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Db(err) => Some(err),
+            Error::Image(err) => Some(err),
+            Error::Io(err) => Some(err),
+            Error::Clipboard(_)
+            | Error::Platform(_)
+            | Error::SchemaTooNew { .. }
+            | Error::InvalidBackup(_)
+            | Error::WrongKey => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Self {
+        Error::Db(err)
+    }
+}
+
+impl From<arboard::Error> for Error {
+    fn from(err: arboard::Error) -> Self {
+        Error::Clipboard(err.to_string())
+    }
+}
+
+impl From<image::ImageError> for Error {
+    fn from(err: image::ImageError) -> Self {
+        Error::Image(err)
+    }
+}
+
+impl From<MigrationError> for Error {
+    fn from(err: MigrationError) -> Self {
+        match err {
+            MigrationError::TooNew { found, supported } => Error::SchemaTooNew { found, supported },
+            MigrationError::Sqlite(err) => Error::Db(err),
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Io(err.into())
+    }
+}
+
+impl From<zip::result::ZipError> for Error {
+    fn from(err: zip::result::ZipError) -> Self {
+        match err {
+            zip::result::ZipError::Io(err) => Error::Io(err),
+            err => Error::Io(io::Error::new(io::ErrorKind::InvalidData, err)),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
@@ -1,10 +1,19 @@
 use core_foundation::runloop::{kCFRunLoopDefaultMode, CFRunLoopRunInMode};
-use objc2_app_kit::{NSBitmapImageFileType, NSBitmapImageRep, NSWorkspace};
-use objc2_foundation::NSDictionary;
-use std::env::current_exe;
+use objc2::runtime::ProtocolObject;
+use objc2_app_kit::{
+    NSBitmapImageFileType, NSBitmapImageRep, NSImage, NSPasteboard, NSPasteboardItem, NSWorkspace,
+};
+use objc2_foundation::{NSArray, NSData, NSDictionary, NSString, NSURL};
 use std::fs::File;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::backend::error::{Error, Result};
+use crate::backend::paths;
+
+// Prefix of the cached icons of files, apart from the icons of applications
+const FILE_ICON_PREFIX: &str = "file-";
 
 /// Return the name of the current focused application.
 ///
@@ -31,6 +40,28 @@ pub fn current_focus_app_name() -> String {
     "Unknown".to_string()
 }
 
+/// Return the bundle identifier of the current focused application, `None` if it has none.
+///
+/// # Example
+///
+/// ```
+/// use create::backend::macos::current_focus_app_bundle_id;
+///
+/// println!("{:?}", current_focus_app_bundle_id()); // Output: Some("com.microsoft.VSCode")
+/// ```
+pub fn current_focus_app_bundle_id() -> Option<String> {
+    unsafe {
+        CFRunLoopRunInMode(kCFRunLoopDefaultMode, 0.0, false as _);
+    }
+
+    let workspace = { NSWorkspace::sharedWorkspace() };
+
+    workspace
+        .frontmostApplication()
+        .and_then(|app| app.bundleIdentifier())
+        .map(|id| id.to_string())
+}
+
 /// Return the path of the current focused application.
 ///
 /// # Example
@@ -65,21 +96,21 @@ pub fn current_focus_app_path() -> PathBuf {
 ///
 /// - Icon will be saved as a PNG file.
 /// - Icon file name will be the same as the app name.
+/// - Icon will be cached in the `icons` folder of the data directory.
+///
+/// Fails if the application has no icon, or if the icon cannot be cached.
 ///
 /// # Example
 ///
 /// ```
 /// use create::backend::macos::current_focus_app_icon_path;
 ///
-/// println!("{:?}", current_focus_app_icon_path()); // Output: "/Applications/THIS.app/Contents/MacOS/Code.png"
+/// println!("{:?}", current_focus_app_icon_path()); // Output: Ok("/Users/foo/Library/Application Support/paste-fork/icons/Code.png")
 /// ```
-pub fn current_focus_app_icon_path() -> PathBuf {
+pub fn current_focus_app_icon_path() -> Result<PathBuf> {
     let current_focus_app_name = current_focus_app_name();
-    let current_focus_app_icon_path = current_exe()
-        .unwrap()
-        .parent()
-        .unwrap()
-        .join(format!("{}.png", current_focus_app_name));
+    let current_focus_app_icon_path =
+        paths::icons_dir()?.join(format!("{}.png", current_focus_app_name));
 
     if !current_focus_app_icon_path.exists() {
         unsafe {
@@ -90,29 +121,242 @@ pub fn current_focus_app_icon_path() -> PathBuf {
 
         if let Some(app) = workspace.frontmostApplication() {
             if let Some(icon) = app.icon() {
-                if let Some(tiff_data) = icon.TIFFRepresentation() {
-                    if let Some(bitmap_rep) = NSBitmapImageRep::imageRepWithData(&tiff_data) {
-                        if let Some(png_data) = unsafe {
-                            bitmap_rep.representationUsingType_properties(
-                                NSBitmapImageFileType::PNG,
-                                &NSDictionary::new(),
-                            )
-                        } {
-                            if let Ok(mut file) = File::create(&current_focus_app_icon_path) {
-                                file.write_all(unsafe { png_data.as_bytes_unchecked() })
-                                    .unwrap();
-                            }
-                        }
-                    }
-                }
+                write_png(&icon, &current_focus_app_icon_path)?;
             }
         }
     }
 
-    debug_assert!(
-        current_focus_app_icon_path.exists(),
-        "Icon file should exist after called."
-    );
+    if !current_focus_app_icon_path.exists() {
+        return Err(Error::Platform(format!(
+            "no icon found for {}",
+            current_focus_app_name
+        )));
+    }
+
+    Ok(current_focus_app_icon_path)
+}
+
+/// Return the icon file path of a file, as shown by Finder.
+///
+/// - Icon will be saved as a PNG file.
+/// - Icons are shared by the files of the same extension, and by the folders.
+/// - Icon will be cached in the `icons` folder of the data directory.
+///
+/// Fails if the icon cannot be cached.
+///
+/// # Example
+///
+/// ```
+/// use create::backend::macos::file_icon_path;
+///
+/// println!("{:?}", file_icon_path(Path::new("/Users/foo/report.pdf"), false)); // Output: Ok("/Users/foo/Library/Application Support/paste-fork/icons/file-pdf.png")
+/// ```
+pub fn file_icon_path(path: &Path, is_dir: bool) -> Result<PathBuf> {
+    let kind = if is_dir {
+        "folder".to_string()
+    } else {
+        path.extension().map_or("none".to_string(), |extension| {
+            extension.to_string_lossy().to_lowercase()
+        })
+    };
+    let file_icon_path = paths::icons_dir()?.join(format!("{}{}.png", FILE_ICON_PREFIX, kind));
+
+    if !file_icon_path.exists() {
+        let workspace = NSWorkspace::sharedWorkspace();
+        let icon = workspace.iconForFile(&NSString::from_str(&path.to_string_lossy()));
+
+        write_png(&icon, &file_icon_path)?;
+    }
+
+    if !file_icon_path.exists() {
+        return Err(Error::Platform(format!(
+            "no icon found for {}",
+            path.display()
+        )));
+    }
+
+    Ok(file_icon_path)
+}
+
+/// Return the data the general pasteboard holds for a type.
+///
+/// Used for the types `arboard` does not expose, e.g. `public.rtf` and `public.url`.
+///
+/// # Example
+///
+/// ```
+/// use create::backend::macos::pasteboard_data;
+///
+/// println!("{:?}", pasteboard_data("public.url")); // Output: Some([104, 116, 116, 112, ...])
+/// ```
+pub fn pasteboard_data(pasteboard_type: &str) -> Option<Vec<u8>> {
+    let pasteboard = NSPasteboard::generalPasteboard();
+
+    pasteboard
+        .dataForType(&NSString::from_str(pasteboard_type))
+        .map(|data| data.to_vec())
+}
+
+/// Return the types of the contents of the general pasteboard.
+///
+/// # Example
+///
+/// ```
+/// use create::backend::macos::pasteboard_types;
+///
+/// println!("{:?}", pasteboard_types()); // Output: ["public.utf8-plain-text", "org.nspasteboard.ConcealedType"]
+/// ```
+pub fn pasteboard_types() -> Vec<String> {
+    let pasteboard = NSPasteboard::generalPasteboard();
+
+    pasteboard
+        .types()
+        .map(|types| types.iter().map(|t| t.to_string()).collect())
+        .unwrap_or_default()
+}
+
+/// Replace the contents of the general pasteboard, offering every given type at once.
+///
+/// - `flavors` are pairs of a pasteboard type and its data, written to the first pasteboard item.
+/// - `files` are written as file URLs, one pasteboard item per file.
+///
+/// Fails if the pasteboard rejected the contents.
+///
+/// # Example
+///
+/// ```
+/// use create::backend::macos::write_pasteboard;
+///
+/// write_pasteboard(&[("public.utf8-plain-text", b"Hello".to_vec()), ("public.html", b"<b>Hello</b>".to_vec())], &[])?;
+/// ```
+pub fn write_pasteboard(flavors: &[(&str, Vec<u8>)], files: &[impl AsRef<Path>]) -> Result<()> {
+    let mut items = Vec::new();
+    let first_item = NSPasteboardItem::new();
+
+    for (pasteboard_type, data) in flavors {
+        first_item.setData_forType(
+            &NSData::with_bytes(data),
+            &NSString::from_str(pasteboard_type),
+        );
+    }
+    items.push(first_item);
+
+    for (index, file) in files.iter().enumerate() {
+        let url = NSURL::fileURLWithPath(&NSString::from_str(&file.as_ref().to_string_lossy()));
+        let Some(url) = url.absoluteString() else {
+            continue;
+        };
+
+        // The first file shares the item holding the other flavors, like Finder does
+        let item = if index == 0 {
+            &items[0]
+        } else {
+            items.push(NSPasteboardItem::new());
+            &items[items.len() - 1]
+        };
+        item.setData_forType(
+            &NSData::with_bytes(url.to_string().as_bytes()),
+            &NSString::from_str("public.file-url"),
+        );
+    }
+
+    let items = items
+        .into_iter()
+        .map(ProtocolObject::from_retained)
+        .collect::<Vec<_>>();
+    let pasteboard = NSPasteboard::generalPasteboard();
+
+    pasteboard.clearContents();
+    if !pasteboard.writeObjects(&NSArray::from_retained_slice(&items)) {
+        return Err(Error::Clipboard(
+            "the pasteboard rejected the contents".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Hide the frontmost application, giving the focus back to the application below it.
+///
+/// Runs an AppleScript through `osascript`, which needs the Accessibility permission.
+///
+/// # Example
+///
+/// ```
+/// use create::backend::macos::hide_frontmost_app;
+///
+/// hide_frontmost_app()?;
+/// ```
+pub fn hide_frontmost_app() -> Result<()> {
+    let output = Command::new("osascript")
+        .arg("-e")
+        .arg(
+            r#"
+            tell application "System Events"
+                set visible of first process whose frontmost is true to false
+            end tell
+        "#,
+        )
+        .output()?;
+
+    if !output.status.success() {
+        return Err(Error::Platform(format!(
+            "osascript failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Show a modal alert with a title and a message, waiting until it is dismissed.
+///
+/// Used for failures the application cannot start with, when there is no window to report them in.
+///
+/// # Example
+///
+/// ```
+/// use create::backend::macos::show_alert;
+///
+/// show_alert("Paste-Fork cannot start", "database is locked")?;
+/// ```
+pub fn show_alert(title: &str, message: &str) -> Result<()> {
+    // Title and message are passed as arguments, so they never need escaping
+    let output = Command::new("osascript")
+        .args(["-e", "on run argv"])
+        .args([
+            "-e",
+            "display alert (item 1 of argv) message (item 2 of argv) as critical",
+        ])
+        .args(["-e", "end run"])
+        .args([title, message])
+        .output()?;
+
+    if !output.status.success() {
+        return Err(Error::Platform(format!(
+            "osascript failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Save an image as a PNG file, nothing is written if it cannot be converted.
+fn write_png(image: &NSImage, path: &Path) -> Result<()> {
+    if let Some(tiff_data) = image.TIFFRepresentation() {
+        if let Some(bitmap_rep) = NSBitmapImageRep::imageRepWithData(&tiff_data) {
+            if let Some(png_data) = unsafe {
+                bitmap_rep.representationUsingType_properties(
+                    NSBitmapImageFileType::PNG,
+                    &NSDictionary::new(),
+                )
+            } {
+                let mut file = File::create(path)?;
+                file.write_all(unsafe { png_data.as_bytes_unchecked() })?;
+            }
+        }
+    }
 
-    current_focus_app_icon_path
+    Ok(())
 }
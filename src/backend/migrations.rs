@@ -0,0 +1,710 @@
+use rusqlite::types::{ToSqlOutput, ValueRef};
+use rusqlite::{params, Connection, ToSql, Transaction};
+use std::fmt;
+
+use crate::backend::classifier::classify;
+use crate::backend::crypto::{self, Cipher};
+use crate::backend::utils::{self, sha256_hex};
+
+/// A single schema upgrade step, executed inside its own transaction.
+type Migration = fn(&Transaction) -> rusqlite::Result<()>;
+
+/// Ordered list of schema upgrade steps.
+///
+/// The database `PRAGMA user_version` records how many of these steps have been applied.
+/// A released step must never be edited or reordered, append a new one instead.
+const MIGRATIONS: &[Migration] = &[
+    v1_create_history,
+    v2_create_history_fts,
+    v3_create_history_timestamp_index,
+    v4_create_blobs,
+    v5_add_blob_thumbnails,
+    v6_add_history_pinned,
+    v7_create_pinboards,
+    v8_encrypt_contents,
+    v9_create_representations,
+    v10_index_html_contents,
+    v11_index_all_text_contents,
+    v12_add_history_subtype,
+    v13_add_history_usage,
+    v14_add_history_trash,
+    v15_add_history_expires_at,
+    v16_add_history_secrets,
+    v17_unindex_secrets,
+    v18_unindex_trash,
+];
+
+// Steps that drop plaintext copies of the contents, the file is rebuilt after them so no free page keeps one
+const VACUUM_AFTER: &[i64] = &[8, 17, 18];
+
+/// The schema version this binary is built against.
+pub const SCHEMA_VERSION: i64 = MIGRATIONS.len() as i64;
+
+#[derive(Debug)]
+pub enum MigrationError {
+    /// The database was written by a newer binary, opening it could corrupt the history.
+    TooNew { found: i64, supported: i64 },
+    Sqlite(rusqlite::Error),
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::TooNew { found, supported } => write!(
+                f,
+                "database schema version {} is newer than the supported version {}",
+                found, supported
+            ),
+            MigrationError::Sqlite(err) => write!(f, "migration failed: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+impl From<rusqlite::Error> for MigrationError {
+    fn from(err: rusqlite::Error) -> Self {
+        MigrationError::Sqlite(err)
+    }
+}
+
+/// Upgrade the database schema to `SCHEMA_VERSION`.
+///
+/// Each pending step runs in its own transaction together with the `user_version` bump,
+/// so an interrupted upgrade resumes from the last completed step on the next startup.
+/// The database is vacuumed once done if a step dropped plaintext contents, see `VACUUM_AFTER`.
+///
+/// # Errors
+/// Returns `MigrationError::TooNew` without touching the database
+/// if its schema version is newer than this binary understands.
+///
+/// # Example
+/// ```
+/// use crate::backend::migrations;
+///
+/// let mut conn = rusqlite::Connection::open("clipboard.db")?;
+/// migrations::migrate(&mut conn)?;
+/// ```
+pub fn migrate(conn: &mut Connection) -> Result<(), MigrationError> {
+    let current: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+    if current > SCHEMA_VERSION {
+        return Err(MigrationError::TooNew {
+            found: current,
+            supported: SCHEMA_VERSION,
+        });
+    }
+
+    for (index, step) in MIGRATIONS.iter().enumerate().skip(current as usize) {
+        let version = index as i64 + 1;
+        let tx = conn.transaction()?;
+
+        step(&tx)?;
+        tx.pragma_update(None, "user_version", version)?;
+        tx.commit()?;
+
+        log::info!("Migrated clipboard database to schema version {}", version);
+    }
+
+    // A new database has nothing to drop
+    if current > 0 && VACUUM_AFTER.iter().any(|version| *version > current) {
+        conn.execute_batch("VACUUM")?;
+    }
+
+    Ok(())
+}
+
+// ------------------------------------------------------------------
+//                            MIGRATIONS
+// ------------------------------------------------------------------
+/// v1: The original `history` table.
+///
+/// Databases created before versioning existed already have this table at `user_version = 0`,
+/// hence `IF NOT EXISTS`.
+fn v1_create_history(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS history (
+                id INTEGER PRIMARY KEY,
+                source_app TEXT NOT NULL,
+                icon_path TEXT NOT NULL,
+                content_type TEXT NOT NULL,
+                content BLOB NOT NULL,
+                timestamp TEXT NOT NULL DEFAULT (DATETIME('NOW', 'UTC'))
+    )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// v2: FTS5 index over the text contents and source app of every record.
+///
+/// Image records are indexed with an empty `content` so they can still be found by their source app.
+/// Triggers keep the index in sync, existing records are backfilled.
+fn v2_create_history_fts(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE VIRTUAL TABLE history_fts USING fts5(
+            content,
+            source_app,
+            tokenize = 'unicode61 remove_diacritics 2'
+        );
+
+        INSERT INTO history_fts (rowid, content, source_app)
+        SELECT id, CASE WHEN content_type = 'TEXT' THEN content ELSE '' END, source_app
+        FROM history;
+
+        CREATE TRIGGER history_fts_insert AFTER INSERT ON history BEGIN
+            INSERT INTO history_fts (rowid, content, source_app)
+            VALUES (new.id, CASE WHEN new.content_type = 'TEXT' THEN new.content ELSE '' END, new.source_app);
+        END;
+
+        CREATE TRIGGER history_fts_delete AFTER DELETE ON history BEGIN
+            DELETE FROM history_fts WHERE rowid = old.id;
+        END;
+
+        CREATE TRIGGER history_fts_update AFTER UPDATE OF content_type, content, source_app ON history BEGIN
+            DELETE FROM history_fts WHERE rowid = old.id;
+            INSERT INTO history_fts (rowid, content, source_app)
+            VALUES (new.id, CASE WHEN new.content_type = 'TEXT' THEN new.content ELSE '' END, new.source_app);
+        END;",
+    )
+}
+
+/// v3: Index matching the history order, for keyset pagination.
+fn v3_create_history_timestamp_index(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute(
+        "CREATE INDEX history_timestamp_idx ON history (timestamp DESC, id DESC)",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// v4: Content-addressed storage for image contents.
+///
+/// Image records point at a row of `blobs` keyed by the SHA-256 hash of the PNG bytes,
+/// and keep an empty `content`. A blob is released together with the last record referencing it.
+fn v4_create_blobs(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE blobs (
+            hash TEXT PRIMARY KEY,
+            data BLOB NOT NULL
+        );
+
+        ALTER TABLE history ADD COLUMN blob_hash TEXT REFERENCES blobs (hash);
+
+        CREATE INDEX history_blob_hash_idx ON history (blob_hash);
+
+        CREATE TRIGGER blobs_release AFTER DELETE ON history WHEN old.blob_hash IS NOT NULL BEGIN
+            DELETE FROM blobs
+            WHERE hash = old.blob_hash
+              AND NOT EXISTS (SELECT 1 FROM history WHERE blob_hash = old.blob_hash);
+        END;",
+    )?;
+
+    // Move the existing images into the blob store one at a time, they can be large
+    let image_ids = tx
+        .prepare("SELECT id FROM history WHERE content_type = 'IMAGE'")?
+        .query_map([], |row| row.get::<_, i64>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    for id in image_ids {
+        let data: Vec<u8> =
+            tx.query_row("SELECT content FROM history WHERE id = ?1", params![id], |row| {
+                row.get(0)
+            })?;
+        let hash = sha256_hex(&data);
+
+        tx.execute(
+            "INSERT OR IGNORE INTO blobs (hash, data) VALUES (?1, ?2)",
+            params![hash, data],
+        )?;
+        tx.execute(
+            "UPDATE history SET blob_hash = ?1, content = x'' WHERE id = ?2",
+            params![hash, id],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// v5: Thumbnails of the stored images, displayed instead of the originals.
+///
+/// `thumbnail` stays `NULL` for images that are already small enough to be displayed as is.
+fn v5_add_blob_thumbnails(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute("ALTER TABLE blobs ADD COLUMN thumbnail BLOB", [])?;
+
+    let hashes = tx
+        .prepare("SELECT hash FROM blobs")?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    for hash in hashes {
+        let data: Vec<u8> =
+            tx.query_row("SELECT data FROM blobs WHERE hash = ?1", params![hash], |row| {
+                row.get(0)
+            })?;
+        let thumbnail = image::load_from_memory(&data)
+            .ok()
+            .and_then(|image| thumbnail_png(&image.to_rgba8()));
+
+        if thumbnail.is_some() {
+            tx.execute(
+                "UPDATE blobs SET thumbnail = ?1 WHERE hash = ?2",
+                params![thumbnail, hash],
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// v6: Pinned records, listed first and exempt from retention.
+fn v6_add_history_pinned(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE history ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;
+
+        CREATE INDEX history_pinned_idx ON history (timestamp DESC, id DESC) WHERE pinned = 1;",
+    )
+}
+
+/// v7: Pinboards, named collections of records.
+///
+/// A record can be on many pinboards, memberships are removed together with either side.
+fn v7_create_pinboards(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE pinboards (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE
+        );
+
+        CREATE TABLE pinboard_items (
+            pinboard_id INTEGER NOT NULL REFERENCES pinboards (id),
+            history_id INTEGER NOT NULL REFERENCES history (id),
+            PRIMARY KEY (pinboard_id, history_id)
+        ) WITHOUT ROWID;
+
+        CREATE INDEX pinboard_items_history_idx ON pinboard_items (history_id);
+
+        CREATE TRIGGER pinboard_items_release_pinboard AFTER DELETE ON pinboards BEGIN
+            DELETE FROM pinboard_items WHERE pinboard_id = old.id;
+        END;
+
+        CREATE TRIGGER pinboard_items_release_history AFTER DELETE ON history BEGIN
+            DELETE FROM pinboard_items WHERE history_id = old.id;
+        END;",
+    )
+}
+
+/// v8: Encryption of the contents at rest.
+///
+/// Text contents and blobs are encrypted with `crypto::cipher`. Since the ciphertexts are randomized,
+/// text records get a `content_hash` fingerprint for deduplication, and blobs are rekeyed by fingerprint.
+///
+/// The FTS index becomes contentless so it no longer holds a plaintext copy of every record,
+/// its triggers read the contents through the `decrypt_text` SQL function.
+fn v8_encrypt_contents(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "DROP TRIGGER history_fts_insert;
+        DROP TRIGGER history_fts_delete;
+        DROP TRIGGER history_fts_update;
+        DROP TABLE history_fts;
+
+        ALTER TABLE history ADD COLUMN content_hash TEXT;
+
+        CREATE INDEX history_content_hash_idx ON history (content_type, content_hash);",
+    )?;
+
+    let text_ids = tx
+        .prepare("SELECT id FROM history WHERE content_type = 'TEXT'")?
+        .query_map([], |row| row.get::<_, i64>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    for id in text_ids {
+        let content = tx.query_row(
+            "SELECT content FROM history WHERE id = ?1",
+            params![id],
+            |row| {
+                Ok(match row.get_ref(0)? {
+                    ValueRef::Text(bytes) | ValueRef::Blob(bytes) => bytes.to_vec(),
+                    _ => Vec::new(),
+                })
+            },
+        )?;
+
+        tx.execute(
+            "UPDATE history SET content = ?1, content_hash = ?2 WHERE id = ?3",
+            params![
+                cipher().encrypt(&content),
+                cipher().fingerprint(&content),
+                id
+            ],
+        )?;
+    }
+
+    // Blobs are keyed by a plain SHA-256 so far, rekey them by fingerprint one at a time
+    let hashes = tx
+        .prepare("SELECT hash FROM blobs")?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    for hash in hashes {
+        let (data, thumbnail): (Vec<u8>, Option<Vec<u8>>) = tx.query_row(
+            "SELECT data, thumbnail FROM blobs WHERE hash = ?1",
+            params![hash],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        let new_hash = cipher().fingerprint(&data);
+
+        tx.execute(
+            "INSERT INTO blobs (hash, data, thumbnail) VALUES (?1, ?2, ?3)",
+            params![
+                new_hash,
+                cipher().encrypt(&data),
+                thumbnail.map(|thumbnail| cipher().encrypt(&thumbnail))
+            ],
+        )?;
+        tx.execute(
+            "UPDATE history SET blob_hash = ?1 WHERE blob_hash = ?2",
+            params![new_hash, hash],
+        )?;
+        tx.execute("DELETE FROM blobs WHERE hash = ?1", params![hash])?;
+    }
+
+    tx.execute_batch(
+        "CREATE VIRTUAL TABLE history_fts USING fts5(
+            content,
+            source_app,
+            content = '',
+            tokenize = 'unicode61 remove_diacritics 2'
+        );
+
+        INSERT INTO history_fts (rowid, content, source_app)
+        SELECT id, CASE WHEN content_type = 'TEXT' THEN decrypt_text(content) ELSE '' END, source_app
+        FROM history;
+
+        CREATE TRIGGER history_fts_insert AFTER INSERT ON history BEGIN
+            INSERT INTO history_fts (rowid, content, source_app)
+            VALUES (new.id, CASE WHEN new.content_type = 'TEXT' THEN decrypt_text(new.content) ELSE '' END, new.source_app);
+        END;
+
+        CREATE TRIGGER history_fts_delete AFTER DELETE ON history BEGIN
+            INSERT INTO history_fts (history_fts, rowid, content, source_app)
+            VALUES ('delete', old.id, CASE WHEN old.content_type = 'TEXT' THEN decrypt_text(old.content) ELSE '' END, old.source_app);
+        END;
+
+        CREATE TRIGGER history_fts_update AFTER UPDATE OF content_type, content, source_app ON history BEGIN
+            INSERT INTO history_fts (history_fts, rowid, content, source_app)
+            VALUES ('delete', old.id, CASE WHEN old.content_type = 'TEXT' THEN decrypt_text(old.content) ELSE '' END, old.source_app);
+            INSERT INTO history_fts (rowid, content, source_app)
+            VALUES (new.id, CASE WHEN new.content_type = 'TEXT' THEN decrypt_text(new.content) ELSE '' END, new.source_app);
+        END;",
+    )
+}
+
+/// v9: Every representation (plain text, HTML, RTF, image, file list, URL) of a clip.
+///
+/// `history` keeps the primary representation for listing and searching.
+/// Contents are encrypted like `history.content`, images point at `blobs` and keep an empty `content`.
+/// A blob is now released once neither a record nor a representation references it.
+fn v9_create_representations(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE representations (
+            history_id INTEGER NOT NULL REFERENCES history (id),
+            kind TEXT NOT NULL,
+            content BLOB NOT NULL,
+            blob_hash TEXT REFERENCES blobs (hash),
+            PRIMARY KEY (history_id, kind)
+        ) WITHOUT ROWID;
+
+        CREATE INDEX representations_blob_hash_idx ON representations (blob_hash);
+
+        INSERT INTO representations (history_id, kind, content, blob_hash)
+        SELECT id, content_type, content, blob_hash
+        FROM history;
+
+        CREATE TRIGGER representations_release_history AFTER DELETE ON history BEGIN
+            DELETE FROM representations WHERE history_id = old.id;
+        END;
+
+        DROP TRIGGER blobs_release;
+
+        CREATE TRIGGER blobs_release AFTER DELETE ON history WHEN old.blob_hash IS NOT NULL BEGIN
+            DELETE FROM blobs
+            WHERE hash = old.blob_hash
+              AND NOT EXISTS (SELECT 1 FROM history WHERE blob_hash = old.blob_hash)
+              AND NOT EXISTS (SELECT 1 FROM representations WHERE blob_hash = old.blob_hash);
+        END;
+
+        CREATE TRIGGER representations_release_blob AFTER DELETE ON representations WHEN old.blob_hash IS NOT NULL BEGIN
+            DELETE FROM blobs
+            WHERE hash = old.blob_hash
+              AND NOT EXISTS (SELECT 1 FROM history WHERE blob_hash = old.blob_hash)
+              AND NOT EXISTS (SELECT 1 FROM representations WHERE blob_hash = old.blob_hash);
+        END;",
+    )
+}
+
+/// v10: Index the plain text of HTML records like text records.
+fn v10_index_html_contents(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "DROP TRIGGER history_fts_insert;
+        DROP TRIGGER history_fts_delete;
+        DROP TRIGGER history_fts_update;
+
+        CREATE TRIGGER history_fts_insert AFTER INSERT ON history BEGIN
+            INSERT INTO history_fts (rowid, content, source_app)
+            VALUES (new.id, CASE WHEN new.content_type IN ('TEXT', 'HTML') THEN decrypt_text(new.content) ELSE '' END, new.source_app);
+        END;
+
+        CREATE TRIGGER history_fts_delete AFTER DELETE ON history BEGIN
+            INSERT INTO history_fts (history_fts, rowid, content, source_app)
+            VALUES ('delete', old.id, CASE WHEN old.content_type IN ('TEXT', 'HTML') THEN decrypt_text(old.content) ELSE '' END, old.source_app);
+        END;
+
+        CREATE TRIGGER history_fts_update AFTER UPDATE OF content_type, content, source_app ON history BEGIN
+            INSERT INTO history_fts (history_fts, rowid, content, source_app)
+            VALUES ('delete', old.id, CASE WHEN old.content_type IN ('TEXT', 'HTML') THEN decrypt_text(old.content) ELSE '' END, old.source_app);
+            INSERT INTO history_fts (rowid, content, source_app)
+            VALUES (new.id, CASE WHEN new.content_type IN ('TEXT', 'HTML') THEN decrypt_text(new.content) ELSE '' END, new.source_app);
+        END;",
+    )
+}
+
+/// v11: Index the plain text of every record but images, e.g. the paths of file lists.
+fn v11_index_all_text_contents(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "DROP TRIGGER history_fts_insert;
+        DROP TRIGGER history_fts_delete;
+        DROP TRIGGER history_fts_update;
+
+        CREATE TRIGGER history_fts_insert AFTER INSERT ON history BEGIN
+            INSERT INTO history_fts (rowid, content, source_app)
+            VALUES (new.id, CASE WHEN new.content_type <> 'IMAGE' THEN decrypt_text(new.content) ELSE '' END, new.source_app);
+        END;
+
+        CREATE TRIGGER history_fts_delete AFTER DELETE ON history BEGIN
+            INSERT INTO history_fts (history_fts, rowid, content, source_app)
+            VALUES ('delete', old.id, CASE WHEN old.content_type <> 'IMAGE' THEN decrypt_text(old.content) ELSE '' END, old.source_app);
+        END;
+
+        CREATE TRIGGER history_fts_update AFTER UPDATE OF content_type, content, source_app ON history BEGIN
+            INSERT INTO history_fts (history_fts, rowid, content, source_app)
+            VALUES ('delete', old.id, CASE WHEN old.content_type <> 'IMAGE' THEN decrypt_text(old.content) ELSE '' END, old.source_app);
+            INSERT INTO history_fts (rowid, content, source_app)
+            VALUES (new.id, CASE WHEN new.content_type <> 'IMAGE' THEN decrypt_text(new.content) ELSE '' END, new.source_app);
+        END;",
+    )
+}
+
+/// v12: What the text of each record holds (URL, email, code...), see `classifier::classify`.
+///
+/// `language` is the guessed language of code. Existing text records are classified.
+fn v12_add_history_subtype(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE history ADD COLUMN subtype TEXT;
+        ALTER TABLE history ADD COLUMN language TEXT;
+
+        CREATE INDEX history_subtype_idx ON history (subtype, timestamp DESC, id DESC);",
+    )?;
+
+    let text_ids = tx
+        .prepare("SELECT id FROM history WHERE content_type IN ('TEXT', 'HTML')")?
+        .query_map([], |row| row.get::<_, i64>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    for id in text_ids {
+        let sealed: Vec<u8> = tx.query_row(
+            "SELECT content FROM history WHERE id = ?1",
+            params![id],
+            |row| row.get(0),
+        )?;
+        // A record that cannot be decrypted is left unclassified
+        let Ok(content) = cipher().decrypt(&sealed) else {
+            continue;
+        };
+        let subtype = classify(&String::from_utf8_lossy(&content));
+
+        tx.execute(
+            "UPDATE history SET subtype = ?1, language = ?2 WHERE id = ?3",
+            params![subtype.as_str(), subtype.language(), id],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// v13: Usage statistics of each record, when it was first and last copied, last pasted, and how often.
+///
+/// `timestamp` is kept as the time the record was last used, copied or pasted.
+/// Existing records are counted as copied once, at their `timestamp`.
+fn v13_add_history_usage(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE history ADD COLUMN created_at TEXT;
+        ALTER TABLE history ADD COLUMN last_copied_at TEXT;
+        ALTER TABLE history ADD COLUMN last_pasted_at TEXT;
+        ALTER TABLE history ADD COLUMN copy_count INTEGER NOT NULL DEFAULT 1;
+        ALTER TABLE history ADD COLUMN paste_count INTEGER NOT NULL DEFAULT 0;
+
+        UPDATE history SET created_at = timestamp, last_copied_at = timestamp;",
+    )
+}
+
+/// v14: The trash, `deleted_at` is when a record has been moved to it, `NULL` for records in the history.
+///
+/// Trashed records are neither listed nor searched, they are deleted for good once the trash period is over.
+fn v14_add_history_trash(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE history ADD COLUMN deleted_at TEXT;
+
+        CREATE INDEX history_deleted_at_idx ON history (deleted_at) WHERE deleted_at IS NOT NULL;",
+    )
+}
+
+/// v15: `expires_at` is when a record copied from an app with an expiring capture rule is deleted, `NULL` for the others.
+fn v15_add_history_expires_at(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE history ADD COLUMN expires_at TEXT;
+
+        CREATE INDEX history_expires_at_idx ON history (expires_at) WHERE expires_at IS NOT NULL;",
+    )
+}
+
+/// v16: `secrets` holds the kinds of secrets found in a record when it was copied, one per line, `NULL` for none.
+fn v16_add_history_secrets(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch("ALTER TABLE history ADD COLUMN secrets TEXT;")
+}
+
+/// v17: The text of records holding a secret is left out of the search index, they are only found by their source app.
+///
+/// The index is rebuilt so it drops the words of the secrets indexed so far.
+/// Deleted words are removed from the index right away where SQLite supports it (3.44+),
+/// instead of lingering until its segments are merged.
+fn v17_unindex_secrets(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "DROP TRIGGER history_fts_insert;
+        DROP TRIGGER history_fts_delete;
+        DROP TRIGGER history_fts_update;
+
+        INSERT INTO history_fts (history_fts) VALUES ('delete-all');
+
+        INSERT INTO history_fts (rowid, content, source_app)
+        SELECT id, CASE WHEN content_type <> 'IMAGE' AND secrets IS NULL THEN decrypt_text(content) ELSE '' END, source_app
+        FROM history;
+
+        CREATE TRIGGER history_fts_insert AFTER INSERT ON history BEGIN
+            INSERT INTO history_fts (rowid, content, source_app)
+            VALUES (new.id, CASE WHEN new.content_type <> 'IMAGE' AND new.secrets IS NULL THEN decrypt_text(new.content) ELSE '' END, new.source_app);
+        END;
+
+        CREATE TRIGGER history_fts_delete AFTER DELETE ON history BEGIN
+            INSERT INTO history_fts (history_fts, rowid, content, source_app)
+            VALUES ('delete', old.id, CASE WHEN old.content_type <> 'IMAGE' AND old.secrets IS NULL THEN decrypt_text(old.content) ELSE '' END, old.source_app);
+        END;
+
+        CREATE TRIGGER history_fts_update AFTER UPDATE OF content_type, content, source_app, secrets ON history BEGIN
+            INSERT INTO history_fts (history_fts, rowid, content, source_app)
+            VALUES ('delete', old.id, CASE WHEN old.content_type <> 'IMAGE' AND old.secrets IS NULL THEN decrypt_text(old.content) ELSE '' END, old.source_app);
+            INSERT INTO history_fts (rowid, content, source_app)
+            VALUES (new.id, CASE WHEN new.content_type <> 'IMAGE' AND new.secrets IS NULL THEN decrypt_text(new.content) ELSE '' END, new.source_app);
+        END;",
+    )?;
+
+    // Older SQLite versions reject the option, deleted words then linger until the segments are merged
+    if let Err(err) = tx.execute(
+        "INSERT INTO history_fts (history_fts, rank) VALUES ('secure-delete', 1)",
+        [],
+    ) {
+        log::info!("The search index does not support secure-delete: {}", err);
+    }
+
+    Ok(())
+}
+
+/// v18: The text of trashed records is left out of the search index too, it is indexed again once restored.
+fn v18_unindex_trash(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "DROP TRIGGER history_fts_insert;
+        DROP TRIGGER history_fts_delete;
+        DROP TRIGGER history_fts_update;
+
+        INSERT INTO history_fts (history_fts) VALUES ('delete-all');
+
+        INSERT INTO history_fts (rowid, content, source_app)
+        SELECT id, CASE WHEN content_type <> 'IMAGE' AND secrets IS NULL AND deleted_at IS NULL THEN decrypt_text(content) ELSE '' END, source_app
+        FROM history;
+
+        CREATE TRIGGER history_fts_insert AFTER INSERT ON history BEGIN
+            INSERT INTO history_fts (rowid, content, source_app)
+            VALUES (new.id, CASE WHEN new.content_type <> 'IMAGE' AND new.secrets IS NULL AND new.deleted_at IS NULL THEN decrypt_text(new.content) ELSE '' END, new.source_app);
+        END;
+
+        CREATE TRIGGER history_fts_delete AFTER DELETE ON history BEGIN
+            INSERT INTO history_fts (history_fts, rowid, content, source_app)
+            VALUES ('delete', old.id, CASE WHEN old.content_type <> 'IMAGE' AND old.secrets IS NULL AND old.deleted_at IS NULL THEN decrypt_text(old.content) ELSE '' END, old.source_app);
+        END;
+
+        CREATE TRIGGER history_fts_update AFTER UPDATE OF content_type, content, source_app, secrets, deleted_at ON history BEGIN
+            INSERT INTO history_fts (history_fts, rowid, content, source_app)
+            VALUES ('delete', old.id, CASE WHEN old.content_type <> 'IMAGE' AND old.secrets IS NULL AND old.deleted_at IS NULL THEN decrypt_text(old.content) ELSE '' END, old.source_app);
+            INSERT INTO history_fts (rowid, content, source_app)
+            VALUES (new.id, CASE WHEN new.content_type <> 'IMAGE' AND new.secrets IS NULL AND new.deleted_at IS NULL THEN decrypt_text(new.content) ELSE '' END, new.source_app);
+        END;",
+    )
+}
+
+// ------------------------------------------------------------------
+//                             INTERNAL
+// ------------------------------------------------------------------
+
+/// `utils::thumbnail_png` with the signature the released steps were written against,
+/// an image that fails to encode gets no thumbnail.
+fn thumbnail_png(image: &image::RgbaImage) -> Option<Vec<u8>> {
+    utils::thumbnail_png(image).ok().flatten()
+}
+
+/// `crypto::cipher` as the released steps call it, see `StepCipher`.
+///
+/// `SqliteStore::open` loads the key before migrating, so it only fails if that has been skipped.
+fn cipher() -> StepCipher {
+    StepCipher(crypto::cipher().map_err(|err| err.to_string()))
+}
+
+/// The cipher with the infallible signatures the released steps were written against.
+///
+/// A failure surfaces when the value is bound to a statement, failing the step.
+struct StepCipher(Result<&'static Cipher, String>);
+
+impl StepCipher {
+    fn encrypt(&self, plaintext: &[u8]) -> StepValue<Vec<u8>> {
+        StepValue(
+            self.0
+                .clone()
+                .and_then(|cipher| cipher.encrypt(plaintext).map_err(|err| err.to_string())),
+        )
+    }
+
+    fn fingerprint(&self, plaintext: &[u8]) -> StepValue<String> {
+        StepValue(self.0.clone().map(|cipher| cipher.fingerprint(plaintext)))
+    }
+
+    fn decrypt(&self, sealed: &[u8]) -> Result<Vec<u8>, String> {
+        self.0
+            .clone()
+            .and_then(|cipher| cipher.decrypt(sealed).map_err(|err| err.to_string()))
+    }
+}
+
+/// A value computed by `StepCipher`, binding it fails if computing it did.
+struct StepValue<T>(Result<T, String>);
+
+impl<T: ToSql> ToSql for StepValue<T> {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        match &self.0 {
+            Ok(value) => value.to_sql(),
+            Err(message) => Err(rusqlite::Error::ToSqlConversionFailure(
+                message.clone().into(),
+            )),
+        }
+    }
+}
@@ -1,3 +1,14 @@
+pub mod app_rules;
+pub mod archive;
+pub mod backup;
+pub mod classifier;
 pub mod clipboard;
+pub mod crypto;
+pub mod error;
 pub mod macos;
+pub mod migrations;
+pub mod paths;
+pub mod secrets;
+pub mod settings;
+pub mod store;
 pub mod utils;
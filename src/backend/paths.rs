@@ -0,0 +1,209 @@
+use once_cell::sync::Lazy;
+use std::env::{self, current_exe};
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Environment variable that overrides the data directory.
+pub const DATA_DIR_ENV: &str = "PASTE_FORK_DATA_DIR";
+/// CLI flag that overrides the data directory, takes precedence over `DATA_DIR_ENV`.
+/// Accepts both `--data-dir <path>` and `--data-dir=<path>`.
+pub const DATA_DIR_FLAG: &str = "--data-dir";
+/// CLI flag that overrides the backups directory, e.g. to keep backups on another drive.
+pub const BACKUP_DIR_FLAG: &str = "--backup-dir";
+
+const APP_DIR_NAME: &str = "paste-fork";
+const DB_FILE_NAME: &str = "clipboard.db";
+const ICONS_DIR_NAME: &str = "icons";
+const BACKUPS_DIR_NAME: &str = "backups";
+const APP_RULES_FILE_NAME: &str = "app-rules.json";
+const SETTINGS_FILE_NAME: &str = "settings.json";
+
+static DATA_DIR: Lazy<Option<PathBuf>> = Lazy::new(|| {
+    let dir = resolve_data_dir();
+    log::info!("Using data directory: {:?}", dir);
+    dir
+});
+
+/// Return the directory that holds the clipboard database and cached app icons, creating it if needed.
+///
+/// Resolution order:
+/// 1. The `--data-dir` CLI flag.
+/// 2. The `PASTE_FORK_DATA_DIR` environment variable.
+/// 3. The platform data directory (`$XDG_DATA_HOME/paste-fork` on Linux, `~/Library/Application Support/paste-fork` on macOS).
+/// 4. The directory of the executable, if the platform directory cannot be determined.
+///
+/// Fails if none of them can be determined, or if the directory cannot be created, e.g. it is not writable.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::paths;
+///
+/// println!("{:?}", paths::data_dir()); // Output: Ok("/Users/foo/Library/Application Support/paste-fork")
+/// ```
+pub fn data_dir() -> io::Result<PathBuf> {
+    let dir = DATA_DIR.clone().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "the data directory cannot be determined",
+        )
+    })?;
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Return the path of the SQLite database file.
+pub fn db_path() -> io::Result<PathBuf> {
+    Ok(data_dir()?.join(DB_FILE_NAME))
+}
+
+/// Return the directory where app icons are cached, creating it if needed.
+pub fn icons_dir() -> io::Result<PathBuf> {
+    let dir = data_dir()?.join(ICONS_DIR_NAME);
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Return the directory where backups of the database are written, creating it if needed.
+///
+/// The `--backup-dir` CLI flag, else the configured directory, else the `backups` folder of the data directory.
+///
+/// # Arguments
+///
+/// * `configured` - The directory of the settings, see `settings::Settings::backup_dir`.
+pub fn backups_dir(configured: Option<&Path>) -> io::Result<PathBuf> {
+    let dir = match flag_value(BACKUP_DIR_FLAG)
+        .map(PathBuf::from)
+        .or_else(|| configured.map(Path::to_path_buf))
+    {
+        Some(dir) => dir,
+        None => data_dir()?.join(BACKUPS_DIR_NAME),
+    };
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Return the path of the capture rules file, see `app_rules::load_app_rules`.
+pub fn app_rules_path() -> io::Result<PathBuf> {
+    Ok(data_dir()?.join(APP_RULES_FILE_NAME))
+}
+
+/// Return the path of the settings file, see `settings::load_settings`.
+pub fn settings_path() -> io::Result<PathBuf> {
+    Ok(data_dir()?.join(SETTINGS_FILE_NAME))
+}
+
+/// Move the database and cached icons written by older versions next to the executable
+/// into the data directory.
+///
+/// Only runs when the data directory has no database yet, so it is a no-op after the first run.
+/// Returns the legacy directory if anything has been moved,
+/// callers should rewrite icon paths stored in the database that point into it.
+pub fn migrate_legacy_data() -> io::Result<Option<PathBuf>> {
+    let Some(legacy_dir) = legacy_dir() else {
+        return Ok(None);
+    };
+    let data_dir = data_dir()?;
+    let legacy_db = legacy_dir.join(DB_FILE_NAME);
+    let new_db = db_path()?;
+
+    if legacy_dir == data_dir || new_db.exists() || !legacy_db.exists() {
+        return Ok(None);
+    }
+
+    move_file(&legacy_db, &new_db)?;
+
+    let icons_dir = icons_dir()?;
+    for entry in fs::read_dir(&legacy_dir)? {
+        let path = entry?.path();
+
+        if path.extension().is_some_and(|ext| ext == "png") {
+            if let Some(file_name) = path.file_name() {
+                move_file(&path, &icons_dir.join(file_name))?;
+            }
+        }
+    }
+
+    log::info!(
+        "Moved clipboard data from {:?} to {:?}",
+        legacy_dir,
+        data_dir
+    );
+
+    Ok(Some(legacy_dir))
+}
+
+/// Return the value of a CLI flag, given as `<flag> <value>` or `<flag>=<value>`.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::paths::flag_value;
+///
+/// // paste-fork --data-dir=/tmp/paste
+/// println!("{:?}", flag_value("--data-dir")); // Output: Some("/tmp/paste")
+/// ```
+pub fn flag_value(flag: &str) -> Option<OsString> {
+    let mut args = env::args_os().skip(1);
+
+    while let Some(arg) = args.next() {
+        let arg = arg.to_string_lossy();
+
+        if arg == flag {
+            return args.next();
+        }
+
+        if let Some(value) = arg.strip_prefix(flag).and_then(|s| s.strip_prefix('=')) {
+            return Some(OsString::from(value));
+        }
+    }
+
+    None
+}
+
+// ------------------------------------------------------------------
+//                             INTERNAL
+// ------------------------------------------------------------------
+/// The data directory, see `data_dir`, `None` if neither the platform directory nor the executable can be found.
+fn resolve_data_dir() -> Option<PathBuf> {
+    if let Some(dir) = data_dir_from_args() {
+        return Some(dir);
+    }
+
+    if let Some(dir) = env::var_os(DATA_DIR_ENV).filter(|dir| !dir.is_empty()) {
+        return Some(PathBuf::from(dir));
+    }
+
+    if let Some(dir) = dirs::data_dir() {
+        return Some(dir.join(APP_DIR_NAME));
+    }
+
+    legacy_dir()
+}
+
+fn data_dir_from_args() -> Option<PathBuf> {
+    flag_value(DATA_DIR_FLAG).map(PathBuf::from)
+}
+
+/// The directory older versions stored their data in.
+fn legacy_dir() -> Option<PathBuf> {
+    current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+}
+
+/// Rename a file, falling back to copy-and-delete when crossing file systems.
+///
+/// A copy whose original cannot be deleted still counts as moved, the leftover is only logged.
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to)?;
+        if let Err(err) = fs::remove_file(from) {
+            log::warn!("Failed to delete {:?} after copying it: {}", from, err);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::backend::app_rules::AppAction;
+use crate::backend::backup::BackupPolicy;
+use crate::backend::clipboard::RetentionPolicy;
+use crate::backend::error::Result;
+use crate::backend::paths;
+
+/// Preferences of the application, see `load_settings`.
+///
+/// Every field is optional in `settings.json`, a missing field keeps its default.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Where backups are written, `None` for the `backups` folder of the data directory, see `paths::backups_dir`.
+    ///
+    /// Point it outside the data directory to keep the backups when the data directory is lost.
+    pub backup_dir: Option<PathBuf>,
+    /// How many backups are kept, the oldest are deleted first.
+    pub backups_kept: usize,
+    /// Whether a copy of the encryption key is written next to the backups, see `crypto::export_key`.
+    ///
+    /// Without the key the backups cannot be restored on another machine, but anyone reading them can decrypt them.
+    pub backup_key: bool,
+    /// How long deleted clips stay in the trash, restorable, in seconds, `0` deletes them for good at once.
+    pub trash_period: u64,
+    /// What happens to the clips a password manager marks as concealed or transient, see `clipboard::set_concealed_clips`.
+    ///
+    /// `ignore` by default, `{ "expire_after": 30 }` keeps them for 30 seconds.
+    pub concealed_clips: AppAction,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            backup_dir: None,
+            backups_kept: BackupPolicy::default().keep,
+            backup_key: false,
+            trash_period: RetentionPolicy::default().trash_period.as_secs(),
+            concealed_clips: AppAction::Ignore,
+        }
+    }
+}
+
+impl Settings {
+    /// The backup policy these settings describe, scheduled like the default one.
+    pub fn backup_policy(&self) -> BackupPolicy {
+        BackupPolicy {
+            keep: self.backups_kept,
+            dir: self.backup_dir.clone(),
+            include_key: self.backup_key,
+            ..BackupPolicy::default()
+        }
+    }
+
+    /// The retention policy these settings describe, with the default limits.
+    pub fn retention_policy(&self) -> RetentionPolicy {
+        RetentionPolicy {
+            trash_period: Duration::from_secs(self.trash_period),
+            ..RetentionPolicy::default()
+        }
+    }
+}
+
+/// Reads the settings from `settings.json` in the data directory, see `paths::settings_path`.
+///
+/// The defaults apply when the file does not exist.
+///
+/// Fails if the file cannot be read or parsed.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::settings::load_settings;
+///
+/// // settings.json: { "backup_dir": "/Volumes/Backup/paste-fork", "backups_kept": 30, "trash_period": 0, "concealed_clips": { "expire_after": 30 } }
+/// let settings = load_settings()?;
+/// println!("{}", settings.backups_kept); // Output: 30
+/// ```
+pub fn load_settings() -> Result<Settings> {
+    match fs::read(paths::settings_path()?) {
+        Ok(json) => Ok(serde_json::from_slice(&json)?),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Settings::default()),
+        Err(err) => Err(err.into()),
+    }
+}
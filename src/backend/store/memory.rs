@@ -0,0 +1,705 @@
+use base64::engine::general_purpose;
+use base64::prelude::*;
+use chrono::{DateTime, SubsecRound, Utc};
+use rusqlite::ffi;
+use std::cmp::Reverse;
+use std::io;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::backend::classifier::classify;
+use crate::backend::clipboard::{
+    ClipSource, ContentTypes, Cursor, Item, Pinboard, Representation, RetentionPolicy,
+};
+use crate::backend::error::{Error, Result};
+use crate::backend::store::{
+    prepare_clip, ClipImport, HistoryStore, PrimaryContent, RecordFilter, SearchTerm,
+};
+use crate::backend::utils::{sanitize_html, thumbnail_png};
+
+/// The clipboard history in memory, gone when the store is dropped.
+///
+/// Behaves like `SqliteStore`, except that contents are kept in plain text
+/// and search results are ordered by recency instead of relevance.
+#[derive(Default)]
+pub struct MemoryStore {
+    state: Mutex<State>,
+}
+
+#[derive(Clone, Default)]
+struct State {
+    /// In insertion order, sorted when listed.
+    records: Vec<Record>,
+    pinboards: Vec<Pinboard>,
+    /// Pairs of a pinboard id and a record id.
+    pinboard_items: Vec<(i64, i64)>,
+    last_id: i64,
+    last_pinboard_id: i64,
+}
+
+#[derive(Clone)]
+struct Record {
+    item: Item,
+    representations: Vec<Representation>,
+    /// The primary content the record is deduplicated by, see `HistoryStore::save_clip`.
+    primary: Vec<u8>,
+    /// When the record has been moved to the trash, see `HistoryStore::trash_records`.
+    deleted_at: Option<DateTime<Utc>>,
+    /// When the record is deleted, see `HistoryStore::set_expiry`.
+    expires_at: Option<DateTime<Utc>>,
+}
+
+impl MemoryStore {
+    /// Creates an empty store.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use crate::backend::store::MemoryStore;
+    ///
+    /// let store = MemoryStore::new();
+    /// ```
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+}
+
+impl HistoryStore for MemoryStore {
+    fn save_clip(
+        &self,
+        source: &ClipSource,
+        representations: &[Representation],
+        secrets: &[String],
+    ) -> Result<Option<i64>> {
+        let Some((representations, primary)) = prepare_clip(representations) else {
+            return Ok(None);
+        };
+
+        let mut state = self.state();
+        let primary_bytes = primary_bytes(&primary);
+        let existing = state.position(&primary, &primary_bytes);
+        let (id, pinned) = match existing {
+            Some(index) => (
+                state.records[index].item.id,
+                state.records[index].item.pinned,
+            ),
+            None => (state.last_id + 1, false),
+        };
+
+        // Build the item first, a failure leaves the history untouched
+        let mut item = to_item(id, source, &primary, &representations, pinned)?;
+        item.secrets = secrets.to_vec();
+        if let Some(index) = existing {
+            let previous = state.records.remove(index).item;
+            item.created_at = previous.created_at;
+            item.last_pasted_at = previous.last_pasted_at;
+            item.copy_count = previous.copy_count + 1;
+            item.paste_count = previous.paste_count;
+        } else {
+            state.last_id = id;
+        }
+        state.records.push(Record {
+            item,
+            representations,
+            primary: primary_bytes,
+            deleted_at: None,
+            expires_at: None,
+        });
+
+        Ok(Some(id))
+    }
+
+    fn import_clips(&self, clips: &mut dyn Iterator<Item = ClipImport>) -> Result<usize> {
+        let mut state = self.state();
+        // Imported into a copy first, like a transaction, a failure leaves the history untouched
+        let mut staged = state.clone();
+        let mut imported = 0;
+
+        for clip in clips {
+            if staged.import_clip(&clip)?.is_some() {
+                imported += 1;
+            }
+        }
+        *state = staged;
+
+        Ok(imported)
+    }
+
+    fn get_all_records(&self) -> Result<Vec<Item>> {
+        Ok(self.state().sorted_items(|_| true))
+    }
+
+    fn find_records(&self, filter: &RecordFilter) -> Result<Vec<i64>> {
+        Ok(self
+            .state()
+            .sorted_items(|item| filter.matches(item))
+            .iter()
+            .rev()
+            .map(|item| item.id)
+            .collect())
+    }
+
+    fn get_record(&self, id: i64) -> Result<Option<Item>> {
+        Ok(self
+            .state()
+            .records
+            .iter()
+            .find(|record| record.item.id == id && record.deleted_at.is_none())
+            .map(|record| record.item.clone()))
+    }
+
+    fn get_recent_records(&self, limit: i64) -> Result<Vec<Item>> {
+        let mut items = self.state().sorted_items(|_| true);
+        items.truncate(limit.max(0) as usize);
+
+        Ok(items)
+    }
+
+    fn get_records_page(&self, before: Option<Cursor>, limit: i64) -> Result<Vec<Item>> {
+        let mut items = self.state().sorted_items(|item| {
+            !item.pinned
+                && before.as_ref().is_none_or(|before| {
+                    let cursor = Cursor::from(item);
+                    (&cursor.timestamp, cursor.id) < (&before.timestamp, before.id)
+                })
+        });
+        items.truncate(limit.max(0) as usize);
+
+        Ok(items)
+    }
+
+    fn get_pinned_records(&self) -> Result<Vec<Item>> {
+        Ok(self.state().sorted_items(|item| item.pinned))
+    }
+
+    fn count_records(&self) -> Result<i64> {
+        Ok(self
+            .state()
+            .records
+            .iter()
+            .filter(|record| record.deleted_at.is_none())
+            .count() as i64)
+    }
+
+    /// Words are matched against the words of the text contents and the source app.
+    fn search_text(&self, term: &str) -> Result<Vec<Item>> {
+        let term = SearchTerm::parse(term);
+
+        if term.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        Ok(self.state().sorted_items(|item| {
+            let subtype = item.subtype.as_ref();
+
+            term.subtype
+                .is_none_or(|name| subtype.is_some_and(|subtype| subtype.as_str() == name))
+                && term.language.is_none_or(|name| {
+                    subtype
+                        .and_then(|subtype| subtype.language())
+                        .is_some_and(|language| language.eq_ignore_ascii_case(name))
+                })
+                && matches_words(item, &term.words)
+        }))
+    }
+
+    fn get_representations(&self, id: i64) -> Result<Vec<Representation>> {
+        Ok(self
+            .state()
+            .records
+            .iter()
+            .find(|record| record.item.id == id)
+            .map(|record| record.representations.clone())
+            .unwrap_or_default())
+    }
+
+    fn record_paste(&self, id: i64) -> Result<()> {
+        if let Some(record) = self.state().record_mut(id) {
+            let now = Utc::now().trunc_subsecs(0);
+            record.item.timestamp = now;
+            record.item.last_pasted_at = Some(now);
+            record.item.paste_count += 1;
+        }
+
+        Ok(())
+    }
+
+    fn set_expiry(&self, id: i64, expires_at: DateTime<Utc>) -> Result<()> {
+        if let Some(record) = self.state().record_mut(id) {
+            record.expires_at = Some(expires_at.trunc_subsecs(0));
+        }
+
+        Ok(())
+    }
+
+    fn set_pinned(&self, id: i64, pinned: bool) -> Result<()> {
+        if let Some(record) = self.state().record_mut(id) {
+            record.item.pinned = pinned;
+        }
+
+        Ok(())
+    }
+
+    fn get_pinboards(&self) -> Result<Vec<Pinboard>> {
+        Ok(self.state().pinboards.clone())
+    }
+
+    fn create_pinboard(&self, name: &str) -> Result<i64> {
+        let mut state = self.state();
+
+        if state.pinboards.iter().any(|pinboard| pinboard.name == name) {
+            return Err(unique_violation());
+        }
+
+        state.last_pinboard_id += 1;
+        let id = state.last_pinboard_id;
+        state.pinboards.push(Pinboard {
+            id,
+            name: name.to_string(),
+        });
+
+        Ok(id)
+    }
+
+    fn rename_pinboard(&self, id: i64, name: &str) -> Result<()> {
+        let mut state = self.state();
+
+        if state
+            .pinboards
+            .iter()
+            .any(|pinboard| pinboard.name == name && pinboard.id != id)
+        {
+            return Err(unique_violation());
+        }
+
+        if let Some(pinboard) = state
+            .pinboards
+            .iter_mut()
+            .find(|pinboard| pinboard.id == id)
+        {
+            pinboard.name = name.to_string();
+        }
+
+        Ok(())
+    }
+
+    fn delete_pinboard(&self, id: i64) -> Result<()> {
+        let mut state = self.state();
+
+        state.pinboards.retain(|pinboard| pinboard.id != id);
+        state
+            .pinboard_items
+            .retain(|(pinboard_id, _)| *pinboard_id != id);
+
+        Ok(())
+    }
+
+    fn add_to_pinboard(&self, pinboard_id: i64, id: i64) -> Result<()> {
+        let mut state = self.state();
+
+        // Like the foreign keys of `pinboard_items`, both must exist
+        let exists = state
+            .pinboards
+            .iter()
+            .any(|pinboard| pinboard.id == pinboard_id)
+            && state.records.iter().any(|record| record.item.id == id);
+        if !exists {
+            return Err(Error::Db(rusqlite::Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_CONSTRAINT_FOREIGNKEY),
+                Some("FOREIGN KEY constraint failed".to_string()),
+            )));
+        }
+
+        if !state.pinboard_items.contains(&(pinboard_id, id)) {
+            state.pinboard_items.push((pinboard_id, id));
+        }
+
+        Ok(())
+    }
+
+    fn remove_from_pinboard(&self, pinboard_id: i64, id: i64) -> Result<()> {
+        self.state()
+            .pinboard_items
+            .retain(|item| *item != (pinboard_id, id));
+
+        Ok(())
+    }
+
+    fn get_pinboard_records(&self, pinboard_id: i64) -> Result<Vec<Item>> {
+        let state = self.state();
+        let mut items =
+            state.sorted_items(|item| state.pinboard_items.contains(&(pinboard_id, item.id)));
+
+        // Stable, so most recently used first within pinned and unpinned records
+        items.sort_by_key(|item| !item.pinned);
+
+        Ok(items)
+    }
+
+    fn enforce_retention(&self, policy: &RetentionPolicy) -> Result<usize> {
+        let mut state = self.state();
+        let now = Utc::now();
+        let purged = state
+            .records
+            .iter()
+            .filter(|record| {
+                let trashed = record.deleted_at.is_some_and(|deleted_at| {
+                    now.signed_duration_since(deleted_at)
+                        .to_std()
+                        .unwrap_or_default()
+                        > policy.trash_period
+                });
+                let expired = record
+                    .expires_at
+                    .is_some_and(|expires_at| expires_at <= now)
+                    && !record.item.pinned
+                    && !state
+                        .pinboard_items
+                        .iter()
+                        .any(|(_, id)| *id == record.item.id);
+
+                trashed || expired
+            })
+            .map(|record| record.item.id)
+            .collect::<Vec<_>>();
+        state.remove_records(&purged);
+
+        let evictable = state.sorted_items(|item| {
+            !item.pinned && !state.pinboard_items.iter().any(|(_, id)| *id == item.id)
+        });
+        let mut total_bytes = 0;
+        let mut evicted = Vec::new();
+
+        for (index, item) in evictable.iter().enumerate() {
+            let record = state
+                .records
+                .iter()
+                .find(|record| record.item.id == item.id)
+                .expect("Listed records exist");
+            total_bytes += record
+                .representations
+                .iter()
+                .map(|representation| representation.to_bytes().len() as u64)
+                .sum::<u64>();
+
+            let too_old = policy.max_age.is_some_and(|max_age| {
+                now.signed_duration_since(item.timestamp)
+                    .to_std()
+                    .unwrap_or_default()
+                    > max_age
+            });
+            let too_many = policy.max_items.is_some_and(|max_items| index >= max_items);
+            let too_big = policy
+                .max_total_bytes
+                .is_some_and(|max_total_bytes| total_bytes > max_total_bytes);
+
+            if too_old || too_many || too_big {
+                evicted.push(item.id);
+            }
+        }
+
+        state.remove_records(&evicted);
+
+        Ok(purged.len() + evicted.len())
+    }
+
+    fn trash_records(&self, ids: &[i64]) -> Result<usize> {
+        let mut state = self.state();
+        let now = Utc::now().trunc_subsecs(0);
+        let mut trashed = 0;
+
+        for id in ids {
+            if let Some(record) = state.record_mut(*id) {
+                if record.deleted_at.is_none() {
+                    record.deleted_at = Some(now);
+                    trashed += 1;
+                }
+            }
+        }
+
+        Ok(trashed)
+    }
+
+    fn trash_matching(&self, filter: &RecordFilter) -> Result<Vec<i64>> {
+        let mut state = self.state();
+        let now = Utc::now().trunc_subsecs(0);
+        let ids = state
+            .sorted_items(|item| {
+                !item.pinned
+                    && !state.pinboard_items.iter().any(|(_, id)| *id == item.id)
+                    && filter.matches(item)
+            })
+            .iter()
+            .map(|item| item.id)
+            .collect::<Vec<_>>();
+
+        for id in &ids {
+            if let Some(record) = state.record_mut(*id) {
+                record.deleted_at = Some(now);
+            }
+        }
+
+        Ok(ids)
+    }
+
+    fn untrash_records(&self, ids: &[i64]) -> Result<usize> {
+        let mut state = self.state();
+        let mut untrashed = 0;
+
+        for id in ids {
+            if let Some(record) = state.record_mut(*id) {
+                if record.deleted_at.take().is_some() {
+                    untrashed += 1;
+                }
+            }
+        }
+
+        Ok(untrashed)
+    }
+
+    fn backup(&self, _path: &Path) -> Result<()> {
+        Err(kept_in_memory())
+    }
+
+    fn restore(&self, _path: &Path) -> Result<()> {
+        Err(kept_in_memory())
+    }
+}
+
+impl State {
+    /// Saves a clip exported from another history, see `HistoryStore::import_clips`.
+    ///
+    /// Returns the id of its record, `None` for a clip without text nor image.
+    fn import_clip(&mut self, clip: &ClipImport) -> Result<Option<i64>> {
+        let Some((representations, primary)) = prepare_clip(&clip.representations) else {
+            return Ok(None);
+        };
+
+        let usage = &clip.usage;
+        let primary_bytes = primary_bytes(&primary);
+        let index = match self.position(&primary, &primary_bytes) {
+            Some(index) => {
+                let item = &mut self.records[index].item;
+                item.timestamp = item.timestamp.max(usage.timestamp.trunc_subsecs(0));
+                item.created_at = item.created_at.min(usage.created_at.trunc_subsecs(0));
+                item.last_copied_at = item
+                    .last_copied_at
+                    .max(usage.last_copied_at.trunc_subsecs(0));
+                item.last_pasted_at = item.last_pasted_at.max(
+                    usage
+                        .last_pasted_at
+                        .map(|last_pasted_at| last_pasted_at.trunc_subsecs(0)),
+                );
+                item.copy_count = item.copy_count.max(usage.copy_count);
+                item.paste_count = item.paste_count.max(usage.paste_count);
+                index
+            }
+            None => {
+                let id = self.last_id + 1;
+                let mut item = to_item(id, &clip.source, &primary, &representations, false)?;
+                item.timestamp = usage.timestamp.trunc_subsecs(0);
+                item.created_at = usage.created_at.trunc_subsecs(0);
+                item.last_copied_at = usage.last_copied_at.trunc_subsecs(0);
+                item.last_pasted_at = usage
+                    .last_pasted_at
+                    .map(|last_pasted_at| last_pasted_at.trunc_subsecs(0));
+                item.copy_count = usage.copy_count;
+                item.paste_count = usage.paste_count;
+                item.secrets = clip.secrets.clone();
+
+                self.last_id = id;
+                self.records.push(Record {
+                    item,
+                    representations,
+                    primary: primary_bytes,
+                    deleted_at: None,
+                    expires_at: None,
+                });
+                self.records.len() - 1
+            }
+        };
+
+        let record = &mut self.records[index];
+        record.item.pinned |= clip.pinned;
+        if let Some(expires_at) = clip.expires_at {
+            record.expires_at = Some(expires_at.trunc_subsecs(0));
+        }
+
+        Ok(Some(record.item.id))
+    }
+
+    /// The items of the records kept by `filter`, most recently used first, trashed records excluded.
+    fn sorted_items(&self, filter: impl Fn(&Item) -> bool) -> Vec<Item> {
+        let mut items = self
+            .records
+            .iter()
+            .filter(|record| record.deleted_at.is_none())
+            .map(|record| &record.item)
+            .filter(|item| filter(item))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        items.sort_by_key(|item| Reverse((item.timestamp, item.id)));
+        items
+    }
+
+    /// The index of the record a clip is deduplicated with, see `HistoryStore::save_clip`.
+    fn position(&self, primary: &PrimaryContent, primary_bytes: &[u8]) -> Option<usize> {
+        let content_type = match primary {
+            PrimaryContent::Text(_, content_type) => content_type,
+            PrimaryContent::Image(_) => &ContentTypes::Image,
+        };
+
+        self.records.iter().position(|record| {
+            is_deduplicated_with(&record.item.content_type, content_type)
+                && record.primary == primary_bytes
+        })
+    }
+
+    fn record_mut(&mut self, id: i64) -> Option<&mut Record> {
+        self.records.iter_mut().find(|record| record.item.id == id)
+    }
+
+    /// Deletes records for good, with their pinboard entries like the triggers of `SqliteStore`.
+    fn remove_records(&mut self, ids: &[i64]) {
+        self.records.retain(|record| !ids.contains(&record.item.id));
+        self.pinboard_items.retain(|(_, id)| !ids.contains(id));
+    }
+}
+
+// ------------------------------------------------------------------
+//                             INTERNAL
+// ------------------------------------------------------------------
+
+/// The bytes a record is deduplicated by, its text or PNG encoding.
+fn primary_bytes(primary: &PrimaryContent) -> Vec<u8> {
+    match primary {
+        PrimaryContent::Text(content, _) => content.as_bytes().to_vec(),
+        PrimaryContent::Image(png_bytes) => png_bytes.clone(),
+    }
+}
+
+/// Whether records of these types are deduplicated together, plain and rich text are.
+fn is_deduplicated_with(a: &ContentTypes, b: &ContentTypes) -> bool {
+    matches!(
+        (a, b),
+        (
+            ContentTypes::Text | ContentTypes::Html,
+            ContentTypes::Text | ContentTypes::Html
+        )
+    ) || a == b
+}
+
+/// Builds the item of a record copied for the first time, as `SqliteStore` lists it,
+/// fails if the image cannot be decoded.
+fn to_item(
+    id: i64,
+    source: &ClipSource,
+    primary: &PrimaryContent,
+    representations: &[Representation],
+    pinned: bool,
+) -> Result<Item> {
+    let (content_type, content, subtype) = match primary {
+        PrimaryContent::Text(content, content_type) => {
+            let subtype = match content_type {
+                ContentTypes::Text | ContentTypes::Html => Some(classify(content)),
+                _ => None,
+            };
+            (content_type.clone(), content.clone(), subtype)
+        }
+        PrimaryContent::Image(png_bytes) => {
+            let thumbnail = thumbnail_png(&image::load_from_memory(png_bytes)?.to_rgba8())?;
+            let content =
+                general_purpose::STANDARD.encode(thumbnail.as_deref().unwrap_or(png_bytes));
+            (ContentTypes::Image, content, None)
+        }
+    };
+
+    let html = match content_type {
+        ContentTypes::Html => {
+            representations
+                .iter()
+                .find_map(|representation| match representation {
+                    Representation::Html(html) => Some(sanitize_html(html)),
+                    _ => None,
+                })
+        }
+        _ => None,
+    };
+    let files = match content_type {
+        ContentTypes::Files => representations
+            .iter()
+            .find_map(|representation| match representation {
+                Representation::FileList(files) => Some(files.clone()),
+                _ => None,
+            })
+            .unwrap_or_default(),
+        _ => Vec::new(),
+    };
+
+    let now = Utc::now().trunc_subsecs(0);
+
+    Ok(Item {
+        id,
+        source_app: source.app.clone(),
+        icon_path: source.icon_path.clone(),
+        content_type,
+        content,
+        html,
+        files,
+        subtype,
+        timestamp: now,
+        created_at: now,
+        last_copied_at: now,
+        last_pasted_at: None,
+        copy_count: 1,
+        paste_count: 0,
+        secrets: Vec::new(),
+        pinned,
+    })
+}
+
+/// Whether every word is a prefix of a word of the text content or of the source app,
+/// like the FTS5 query of `SqliteStore::search_text`.
+fn matches_words(item: &Item, words: &[&str]) -> bool {
+    // Like the search index, the text of a clip holding a secret is not searched
+    let content = match item.content_type {
+        ContentTypes::Image => "",
+        _ if !item.secrets.is_empty() => "",
+        _ => item.content.as_str(),
+    };
+    let tokens = tokenize(content)
+        .chain(tokenize(&item.source_app))
+        .collect::<Vec<_>>();
+
+    words
+        .iter()
+        .all(|word| tokenize(word).all(|part| tokens.iter().any(|token| token.starts_with(&part))))
+}
+
+/// Splits text into lowercase alphanumeric words, like the `unicode61` tokenizer of FTS5.
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// The error of backups, nothing copied into a `MemoryStore` is ever written to disk.
+fn kept_in_memory() -> Error {
+    Error::Io(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "the history is kept in memory only",
+    ))
+}
+
+/// The error SQLite returns when a unique constraint fails, e.g. a pinboard name is taken.
+fn unique_violation() -> Error {
+    Error::Db(rusqlite::Error::SqliteFailure(
+        ffi::Error::new(ffi::SQLITE_CONSTRAINT_UNIQUE),
+        Some("UNIQUE constraint failed: pinboards.name".to_string()),
+    ))
+}
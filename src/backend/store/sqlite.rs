@@ -0,0 +1,1111 @@
+use base64::engine::general_purpose;
+use base64::prelude::*;
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use rusqlite::backup::Progress;
+use rusqlite::types::{Type, ValueRef};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Row, MAIN_DB};
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::backend::classifier::{classify, ContentSubtype};
+use crate::backend::clipboard::{
+    ClipSource, ContentTypes, Cursor, Item, Pinboard, Representation, RetentionPolicy,
+};
+use crate::backend::crypto::{self, cipher};
+use crate::backend::error::{Error, Result};
+use crate::backend::migrations::{self, SCHEMA_VERSION};
+use crate::backend::paths;
+use crate::backend::store::{
+    prepare_clip, ClipImport, HistoryStore, PrimaryContent, RecordFilter, SearchTerm,
+};
+use crate::backend::utils::{sanitize_html, thumbnail_png};
+
+// Columns mapped by `row_to_item`, selected from `history h LEFT JOIN blobs b ON b.hash = h.blob_hash`
+// Image contents live in the content-addressed `blobs` table, text contents in `history` itself
+// Images are read as their thumbnail, use `get_representations` for the original
+// HTML and file list records carry their plain text in `history`,
+// their HTML or file list is read from `representations`
+// Contents are encrypted, `row_to_item` decrypts them
+const ITEM_COLUMNS: &str = "h.id, h.source_app, h.icon_path, h.content_type, COALESCE(b.thumbnail, b.data, h.content), h.timestamp, h.pinned,
+    (SELECT r.content FROM representations r WHERE r.history_id = h.id AND r.kind = h.content_type AND h.content_type IN ('HTML', 'FILES')),
+    h.subtype, h.language, h.created_at, h.last_copied_at, h.last_pasted_at, h.copy_count, h.paste_count, h.secrets";
+
+// Conditions on `history` matching a `RecordFilter`, bound with `filter_params`
+const FILTER_CONDITIONS: &str = "(?1 IS NULL OR timestamp >= ?1) AND (?2 IS NULL OR timestamp < ?2)
+    AND (?3 IS NULL OR source_app = ?3) AND (?4 IS NULL OR content_type = ?4)";
+
+/// The clipboard history in an SQLite database, the store of the application.
+///
+/// Contents are encrypted, see `crypto::cipher`, and text contents are indexed for searching (FTS5).
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    /// Opens the database of the data directory, see `paths::db_path`.
+    ///
+    /// Data written by older versions next to the executable is moved into the data directory first.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use crate::backend::store::SqliteStore;
+    ///
+    /// let store = SqliteStore::open_default()?;
+    /// ```
+    pub fn open_default() -> Result<Self> {
+        let legacy_dir = paths::migrate_legacy_data().unwrap_or_else(|err| {
+            log::error!("Failed to move legacy clipboard data: {}", err);
+            None
+        });
+        let store = Self::open(&paths::db_path()?)?;
+
+        // Icons have been moved together with the database, point the history at their new location
+        if let Some(legacy_dir) = legacy_dir {
+            store.conn().execute(
+                "UPDATE history SET icon_path = ?2 || substr(icon_path, length(?1) + 1)
+                 WHERE substr(icon_path, 1, length(?1)) = ?1",
+                params![
+                    legacy_dir.to_string_lossy(),
+                    paths::icons_dir()?.to_string_lossy()
+                ],
+            )?;
+        }
+
+        Ok(store)
+    }
+
+    /// Opens a database file, creating and migrating it if needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the database file.
+    ///
+    /// # Errors
+    /// Fails if the encryption key cannot be loaded, or is missing while the database holds encrypted contents,
+    /// see `crypto::init`, or if the database was written by a newer version, see `migrations::migrate`.
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut conn = Connection::open(path)?;
+
+        crypto::init(!has_encrypted_contents(&conn)?)?;
+        // Deleted contents are overwritten with zeros instead of lingering in free pages
+        conn.pragma_update(None, "secure_delete", true)?;
+        crypto::register_sql_functions(&conn)?;
+        migrations::migrate(&mut conn)?;
+
+        Ok(SqliteStore {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap()
+    }
+}
+
+impl HistoryStore for SqliteStore {
+    fn save_clip(
+        &self,
+        source: &ClipSource,
+        representations: &[Representation],
+        secrets: &[String],
+    ) -> Result<Option<i64>> {
+        let Some((representations, primary)) = prepare_clip(representations) else {
+            return Ok(None);
+        };
+
+        let conn = self.conn();
+        let tx = conn.unchecked_transaction()?;
+
+        let id = match &primary {
+            PrimaryContent::Text(content, content_type) => {
+                save_text(&tx, source, content, content_type, secrets)?
+            }
+            PrimaryContent::Image(png_bytes) => save_image(&tx, source, png_bytes, secrets)?,
+        };
+        replace_representations(&tx, id, &representations)?;
+
+        tx.commit()?;
+
+        Ok(Some(id))
+    }
+
+    fn import_clips(&self, clips: &mut dyn Iterator<Item = ClipImport>) -> Result<usize> {
+        let conn = self.conn();
+        let tx = conn.unchecked_transaction()?;
+        let mut imported = 0;
+
+        for clip in clips {
+            if import_clip(&tx, &clip)?.is_some() {
+                imported += 1;
+            }
+        }
+
+        tx.commit()?;
+
+        Ok(imported)
+    }
+
+    fn get_all_records(&self) -> Result<Vec<Item>> {
+        let conn = self.conn();
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {ITEM_COLUMNS}
+             FROM history h
+             LEFT JOIN blobs b ON b.hash = h.blob_hash
+             WHERE h.deleted_at IS NULL
+             ORDER BY h.timestamp DESC"
+        ))?;
+
+        let history_iter = stmt.query_map(params![], row_to_item)?;
+
+        Ok(history_iter.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn find_records(&self, filter: &RecordFilter) -> Result<Vec<i64>> {
+        let conn = self.conn();
+
+        let ids = conn
+            .prepare(&format!(
+                "SELECT id FROM history
+                 WHERE deleted_at IS NULL AND {FILTER_CONDITIONS}
+                 ORDER BY timestamp, id"
+            ))?
+            .query_map(filter_params(filter), |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<i64>>>()?;
+
+        Ok(ids)
+    }
+
+    fn get_record(&self, id: i64) -> Result<Option<Item>> {
+        let conn = self.conn();
+
+        let item = conn
+            .query_row(
+                &format!(
+                    "SELECT {ITEM_COLUMNS}
+                     FROM history h
+                     LEFT JOIN blobs b ON b.hash = h.blob_hash
+                     WHERE h.id = ?1 AND h.deleted_at IS NULL"
+                ),
+                params![id],
+                row_to_item,
+            )
+            .optional()?;
+
+        Ok(item)
+    }
+
+    fn get_recent_records(&self, limit: i64) -> Result<Vec<Item>> {
+        let conn = self.conn();
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {ITEM_COLUMNS}
+             FROM history h
+             LEFT JOIN blobs b ON b.hash = h.blob_hash
+             WHERE h.deleted_at IS NULL
+             ORDER BY h.timestamp DESC
+             LIMIT ?1"
+        ))?;
+
+        let history_iter = stmt.query_map(params![limit], row_to_item)?;
+
+        Ok(history_iter.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn get_records_page(&self, before: Option<Cursor>, limit: i64) -> Result<Vec<Item>> {
+        let conn = self.conn();
+
+        let mut stmt;
+        let history_iter = match before {
+            Some(cursor) => {
+                stmt = conn.prepare(&format!(
+                    "SELECT {ITEM_COLUMNS}
+                     FROM history h
+                     LEFT JOIN blobs b ON b.hash = h.blob_hash
+                     WHERE h.pinned = 0 AND h.deleted_at IS NULL AND (h.timestamp, h.id) < (?1, ?2)
+                     ORDER BY h.timestamp DESC, h.id DESC
+                     LIMIT ?3"
+                ))?;
+                stmt.query_map(params![cursor.timestamp, cursor.id, limit], row_to_item)?
+            }
+            None => {
+                stmt = conn.prepare(&format!(
+                    "SELECT {ITEM_COLUMNS}
+                     FROM history h
+                     LEFT JOIN blobs b ON b.hash = h.blob_hash
+                     WHERE h.pinned = 0 AND h.deleted_at IS NULL
+                     ORDER BY h.timestamp DESC, h.id DESC
+                     LIMIT ?1"
+                ))?;
+                stmt.query_map(params![limit], row_to_item)?
+            }
+        };
+
+        Ok(history_iter.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn get_pinned_records(&self) -> Result<Vec<Item>> {
+        let conn = self.conn();
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {ITEM_COLUMNS}
+             FROM history h
+             LEFT JOIN blobs b ON b.hash = h.blob_hash
+             WHERE h.pinned = 1 AND h.deleted_at IS NULL
+             ORDER BY h.timestamp DESC, h.id DESC"
+        ))?;
+
+        let history_iter = stmt.query_map(params![], row_to_item)?;
+
+        Ok(history_iter.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn count_records(&self) -> Result<i64> {
+        let conn = self.conn();
+
+        Ok(conn.query_row(
+            "SELECT COUNT(*) FROM history WHERE deleted_at IS NULL",
+            [],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Words are matched through the FTS5 index,
+    /// results are ranked by relevance (bm25), the most relevant first.
+    fn search_text(&self, term: &str) -> Result<Vec<Item>> {
+        let term = SearchTerm::parse(term);
+        let query = fts_query(&term.words);
+
+        if term.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let conn = self.conn();
+
+        let mut stmt;
+        let history_iter = if query.is_empty() {
+            stmt = conn.prepare(&format!(
+                "SELECT {ITEM_COLUMNS}
+                 FROM history h
+                 LEFT JOIN blobs b ON b.hash = h.blob_hash
+                 WHERE h.deleted_at IS NULL
+                   AND (?1 IS NULL OR h.subtype = ?1) AND (?2 IS NULL OR h.language = ?2 COLLATE NOCASE)
+                 ORDER BY h.timestamp DESC, h.id DESC"
+            ))?;
+            stmt.query_map(params![term.subtype, term.language], row_to_item)?
+        } else {
+            stmt = conn.prepare(&format!(
+                "SELECT {ITEM_COLUMNS}
+                 FROM history_fts
+                 JOIN history h ON h.id = history_fts.rowid
+                 LEFT JOIN blobs b ON b.hash = h.blob_hash
+                 WHERE history_fts MATCH ?1 AND h.deleted_at IS NULL
+                   AND (?2 IS NULL OR h.subtype = ?2) AND (?3 IS NULL OR h.language = ?3 COLLATE NOCASE)
+                 ORDER BY bm25(history_fts, 10.0, 1.0), h.timestamp DESC
+                "
+            ))?;
+            stmt.query_map(params![query, term.subtype, term.language], row_to_item)?
+        };
+
+        Ok(history_iter.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn get_representations(&self, id: i64) -> Result<Vec<Representation>> {
+        let conn = self.conn();
+
+        let mut stmt = conn.prepare(
+            "SELECT r.kind, COALESCE(b.data, r.content)
+             FROM representations r
+             LEFT JOIN blobs b ON b.hash = r.blob_hash
+             WHERE r.history_id = ?1",
+        )?;
+
+        let representation_iter = stmt.query_map(params![id], |row| {
+            let kind: String = row.get(0)?;
+            let content = read_content(row.get_ref(1)?, 1)?;
+
+            Ok(Representation::from_bytes(&kind, content))
+        })?;
+
+        Ok(representation_iter
+            .filter_map(|representation| representation.transpose())
+            .collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn record_paste(&self, id: i64) -> Result<()> {
+        let conn = self.conn();
+
+        conn.execute(
+            "UPDATE history
+             SET timestamp = DATETIME('NOW', 'UTC'), last_pasted_at = DATETIME('NOW', 'UTC'), paste_count = paste_count + 1
+             WHERE id = ?1",
+            params![id],
+        )?;
+
+        Ok(())
+    }
+
+    fn set_expiry(&self, id: i64, expires_at: DateTime<Utc>) -> Result<()> {
+        let conn = self.conn();
+
+        conn.execute(
+            "UPDATE history SET expires_at = ?1 WHERE id = ?2",
+            params![format_timestamp(expires_at), id],
+        )?;
+
+        Ok(())
+    }
+
+    fn set_pinned(&self, id: i64, pinned: bool) -> Result<()> {
+        let conn = self.conn();
+
+        conn.execute(
+            "UPDATE history SET pinned = ?1 WHERE id = ?2",
+            params![pinned, id],
+        )?;
+
+        Ok(())
+    }
+
+    fn get_pinboards(&self) -> Result<Vec<Pinboard>> {
+        let conn = self.conn();
+
+        let mut stmt = conn.prepare("SELECT id, name FROM pinboards ORDER BY id")?;
+
+        let pinboard_iter = stmt.query_map(params![], |row| {
+            Ok(Pinboard {
+                id: row.get(0)?,
+                name: row.get(1)?,
+            })
+        })?;
+
+        Ok(pinboard_iter.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn create_pinboard(&self, name: &str) -> Result<i64> {
+        let conn = self.conn();
+
+        conn.execute("INSERT INTO pinboards (name) VALUES (?1)", params![name])?;
+
+        Ok(conn.last_insert_rowid())
+    }
+
+    fn rename_pinboard(&self, id: i64, name: &str) -> Result<()> {
+        let conn = self.conn();
+
+        conn.execute(
+            "UPDATE pinboards SET name = ?1 WHERE id = ?2",
+            params![name, id],
+        )?;
+
+        Ok(())
+    }
+
+    fn delete_pinboard(&self, id: i64) -> Result<()> {
+        let conn = self.conn();
+
+        conn.execute("DELETE FROM pinboards WHERE id = ?1", params![id])?;
+
+        Ok(())
+    }
+
+    fn add_to_pinboard(&self, pinboard_id: i64, id: i64) -> Result<()> {
+        let conn = self.conn();
+
+        conn.execute(
+            "INSERT OR IGNORE INTO pinboard_items (pinboard_id, history_id) VALUES (?1, ?2)",
+            params![pinboard_id, id],
+        )?;
+
+        Ok(())
+    }
+
+    fn remove_from_pinboard(&self, pinboard_id: i64, id: i64) -> Result<()> {
+        let conn = self.conn();
+
+        conn.execute(
+            "DELETE FROM pinboard_items WHERE pinboard_id = ?1 AND history_id = ?2",
+            params![pinboard_id, id],
+        )?;
+
+        Ok(())
+    }
+
+    fn get_pinboard_records(&self, pinboard_id: i64) -> Result<Vec<Item>> {
+        let conn = self.conn();
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {ITEM_COLUMNS}
+             FROM pinboard_items p
+             JOIN history h ON h.id = p.history_id
+             LEFT JOIN blobs b ON b.hash = h.blob_hash
+             WHERE p.pinboard_id = ?1 AND h.deleted_at IS NULL
+             ORDER BY h.pinned DESC, h.timestamp DESC, h.id DESC"
+        ))?;
+
+        let history_iter = stmt.query_map(params![pinboard_id], row_to_item)?;
+
+        Ok(history_iter.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn enforce_retention(&self, policy: &RetentionPolicy) -> Result<usize> {
+        let conn = self.conn();
+        let mut removed = conn.execute(
+            "DELETE FROM history WHERE deleted_at <= DATETIME('NOW', 'UTC', ?1)",
+            params![format!("-{} seconds", policy.trash_period.as_secs())],
+        )?;
+
+        removed += conn.execute(
+            "DELETE FROM history
+             WHERE pinned = 0
+               AND id NOT IN (SELECT history_id FROM pinboard_items)
+               AND expires_at <= DATETIME('NOW', 'UTC')",
+            [],
+        )?;
+
+        if let Some(max_age) = policy.max_age {
+            removed += conn.execute(
+                "DELETE FROM history
+                 WHERE pinned = 0 AND deleted_at IS NULL
+                   AND id NOT IN (SELECT history_id FROM pinboard_items)
+                   AND timestamp < DATETIME('NOW', 'UTC', ?1)",
+                params![format!("-{} seconds", max_age.as_secs())],
+            )?;
+        }
+
+        if let Some(max_items) = policy.max_items {
+            removed += conn.execute(
+                "DELETE FROM history WHERE id IN (
+                    SELECT id FROM history
+                    WHERE pinned = 0 AND deleted_at IS NULL AND id NOT IN (SELECT history_id FROM pinboard_items)
+                    ORDER BY timestamp DESC, id DESC
+                    LIMIT -1 OFFSET ?1
+                )",
+                params![max_items as i64],
+            )?;
+        }
+
+        if let Some(max_total_bytes) = policy.max_total_bytes {
+            removed += conn.execute(
+                "DELETE FROM history WHERE id IN (
+                    SELECT id FROM (
+                        SELECT h.id, SUM((
+                            SELECT COALESCE(SUM(LENGTH(r.content) + COALESCE(LENGTH(b.data) + LENGTH(b.thumbnail), LENGTH(b.data), 0)), 0)
+                            FROM representations r
+                            LEFT JOIN blobs b ON b.hash = r.blob_hash
+                            WHERE r.history_id = h.id
+                        )) OVER (ORDER BY h.timestamp DESC, h.id DESC) AS total_bytes
+                        FROM history h
+                        WHERE h.pinned = 0 AND h.deleted_at IS NULL AND h.id NOT IN (SELECT history_id FROM pinboard_items)
+                    )
+                    WHERE total_bytes > ?1
+                )",
+                params![max_total_bytes as i64],
+            )?;
+        }
+
+        Ok(removed)
+    }
+
+    fn trash_records(&self, ids: &[i64]) -> Result<usize> {
+        let conn = self.conn();
+        let tx = conn.unchecked_transaction()?;
+        let mut trashed = 0;
+
+        for id in ids {
+            trashed += tx.execute(
+                "UPDATE history SET deleted_at = DATETIME('NOW', 'UTC') WHERE id = ?1 AND deleted_at IS NULL",
+                params![id],
+            )?;
+        }
+
+        tx.commit()?;
+
+        Ok(trashed)
+    }
+
+    fn trash_matching(&self, filter: &RecordFilter) -> Result<Vec<i64>> {
+        let conn = self.conn();
+        let tx = conn.unchecked_transaction()?;
+
+        let ids = tx
+            .prepare(&format!(
+                "SELECT id FROM history
+                 WHERE deleted_at IS NULL AND pinned = 0
+                   AND id NOT IN (SELECT history_id FROM pinboard_items)
+                   AND {FILTER_CONDITIONS}
+                 ORDER BY timestamp DESC, id DESC"
+            ))?
+            .query_map(filter_params(filter), |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<i64>>>()?;
+
+        for id in &ids {
+            tx.execute(
+                "UPDATE history SET deleted_at = DATETIME('NOW', 'UTC') WHERE id = ?1",
+                params![id],
+            )?;
+        }
+
+        tx.commit()?;
+
+        Ok(ids)
+    }
+
+    fn untrash_records(&self, ids: &[i64]) -> Result<usize> {
+        let conn = self.conn();
+        let tx = conn.unchecked_transaction()?;
+        let mut untrashed = 0;
+
+        for id in ids {
+            untrashed += tx.execute(
+                "UPDATE history SET deleted_at = NULL WHERE id = ?1 AND deleted_at IS NOT NULL",
+                params![id],
+            )?;
+        }
+
+        tx.commit()?;
+
+        Ok(untrashed)
+    }
+
+    /// Uses the SQLite online backup API, writers wait for the backup to complete.
+    /// Trashed records are left out, they must not outlive their trash period in the backups.
+    fn backup(&self, path: &Path) -> Result<()> {
+        // Written aside first, a backup is never left half written
+        let partial = path.with_extension("partial");
+        let result = self
+            .conn()
+            .backup(MAIN_DB, &partial, None)
+            .map_err(Error::from)
+            .and_then(|()| purge_trash(&partial));
+
+        if let Err(err) = result {
+            let _ = fs::remove_file(&partial);
+            return Err(err);
+        }
+
+        fs::rename(&partial, path)?;
+
+        Ok(())
+    }
+
+    fn restore(&self, path: &Path) -> Result<()> {
+        check_backup(path)?;
+
+        // The restore runs in a single write transaction, an interrupted restore is rolled back
+        let mut conn = self.conn();
+        conn.restore(MAIN_DB, path, None::<fn(Progress)>)?;
+        migrations::migrate(&mut conn)?;
+
+        Ok(())
+    }
+}
+
+// ------------------------------------------------------------------
+//                             INTERNAL
+// ------------------------------------------------------------------
+
+/// Saves text content, returns the id of its record.
+///
+/// The content is stored encrypted, and deduplicated by its keyed fingerprint.
+/// Plain and rich text are deduplicated together, the record takes the type of the latest copy.
+/// File lists are saved as their paths, deduplicated with other file lists only.
+/// Plain and rich text are classified, see `classifier::classify`.
+/// Copying the content again counts the copy, see `Item::copy_count`.
+///
+/// # Arguments
+///
+/// * `conn` - The connection, or transaction, to save with.
+/// * `source` - The application the content has been copied from.
+/// * `content` - The text string to be saved.
+/// * `content_type` - `Text`, `Html` for the plain text of rich text, or `Files` for the paths of a file list.
+/// * `secrets` - The kinds of secrets found in the content, see `HistoryStore::save_clip`.
+fn save_text(
+    conn: &Connection,
+    source: &ClipSource,
+    content: &str,
+    content_type: &ContentTypes,
+    secrets: &[String],
+) -> Result<i64> {
+    let content_hash = cipher()?.fingerprint(content.as_bytes());
+    let subtype = match content_type {
+        ContentTypes::Text | ContentTypes::Html => Some(classify(content)),
+        _ => None,
+    };
+    let language = subtype.as_ref().and_then(ContentSubtype::language);
+    let subtype = subtype.as_ref().map(ContentSubtype::as_str);
+    let secrets = secrets_column(secrets);
+
+    if let Some(id) = find_text(conn, content, content_type)? {
+        conn.execute(
+            "UPDATE history
+             SET timestamp = DATETIME('NOW', 'UTC'), last_copied_at = DATETIME('NOW', 'UTC'), copy_count = copy_count + 1, deleted_at = NULL, expires_at = NULL,
+                 source_app = ?1, icon_path = ?2, content_type = ?3, subtype = ?4, language = ?5, secrets = ?6
+             WHERE id = ?7",
+            params![source.app, source.icon_path, content_type.as_str(), subtype, language, secrets, id],
+        )?;
+
+        return Ok(id);
+    }
+
+    // The secrets are inserted together with the content, so a secret never reaches the search index
+    conn.execute(
+        "INSERT INTO history (source_app, icon_path, content_type, content, content_hash, subtype, language, secrets, created_at, last_copied_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, DATETIME('NOW', 'UTC'), DATETIME('NOW', 'UTC'))",
+        params![source.app, source.icon_path, content_type.as_str(), cipher()?.encrypt(content.as_bytes())?, content_hash, subtype, language, secrets],
+    )?;
+
+    Ok(conn.last_insert_rowid())
+}
+
+/// Saves a clip exported from another history, see `HistoryStore::import_clips`.
+///
+/// Returns the id of its record, `None` for a clip without text nor image.
+fn import_clip(conn: &Connection, clip: &ClipImport) -> Result<Option<i64>> {
+    let Some((representations, primary)) = prepare_clip(&clip.representations) else {
+        return Ok(None);
+    };
+
+    let usage = &clip.usage;
+    let last_pasted_at = usage.last_pasted_at.map(format_timestamp);
+
+    let existing_id = match &primary {
+        PrimaryContent::Text(content, content_type) => find_text(conn, content, content_type)?,
+        PrimaryContent::Image(png_bytes) => find_image(conn, png_bytes)?,
+    };
+
+    let id = match existing_id {
+        Some(id) => {
+            conn.execute(
+                "UPDATE history
+                 SET timestamp = MAX(timestamp, ?1), created_at = MIN(COALESCE(created_at, ?2), ?2),
+                     last_copied_at = MAX(COALESCE(last_copied_at, ?3), ?3),
+                     last_pasted_at = MAX(COALESCE(last_pasted_at, ?4), COALESCE(?4, last_pasted_at)),
+                     copy_count = MAX(copy_count, ?5), paste_count = MAX(paste_count, ?6)
+                 WHERE id = ?7",
+                params![
+                    format_timestamp(usage.timestamp),
+                    format_timestamp(usage.created_at),
+                    format_timestamp(usage.last_copied_at),
+                    last_pasted_at,
+                    usage.copy_count,
+                    usage.paste_count,
+                    id
+                ],
+            )?;
+            id
+        }
+        None => {
+            let id = match &primary {
+                PrimaryContent::Text(content, content_type) => {
+                    save_text(conn, &clip.source, content, content_type, &clip.secrets)?
+                }
+                PrimaryContent::Image(png_bytes) => {
+                    save_image(conn, &clip.source, png_bytes, &clip.secrets)?
+                }
+            };
+            replace_representations(conn, id, &representations)?;
+            conn.execute(
+                "UPDATE history
+                 SET timestamp = ?1, created_at = ?2, last_copied_at = ?3, last_pasted_at = ?4, copy_count = ?5, paste_count = ?6
+                 WHERE id = ?7",
+                params![
+                    format_timestamp(usage.timestamp),
+                    format_timestamp(usage.created_at),
+                    format_timestamp(usage.last_copied_at),
+                    last_pasted_at,
+                    usage.copy_count,
+                    usage.paste_count,
+                    id
+                ],
+            )?;
+            id
+        }
+    };
+
+    if clip.pinned {
+        conn.execute("UPDATE history SET pinned = 1 WHERE id = ?1", params![id])?;
+    }
+    if let Some(expires_at) = clip.expires_at {
+        conn.execute(
+            "UPDATE history SET expires_at = ?1 WHERE id = ?2",
+            params![format_timestamp(expires_at), id],
+        )?;
+    }
+
+    Ok(Some(id))
+}
+
+/// Finds the record of text content, deduplicated as `save_text` does.
+fn find_text(conn: &Connection, content: &str, content_type: &ContentTypes) -> Result<Option<i64>> {
+    let content_hash = cipher()?.fingerprint(content.as_bytes());
+    let deduplicated_types = match content_type {
+        ContentTypes::Text | ContentTypes::Html => ["TEXT", "HTML"],
+        _ => [content_type.as_str(); 2],
+    };
+
+    Ok(conn
+        .query_row(
+            "SELECT id FROM history WHERE content_type IN (?1, ?2) AND content_hash = ?3",
+            params![deduplicated_types[0], deduplicated_types[1], content_hash],
+            |row| row.get(0),
+        )
+        .optional()?)
+}
+
+/// Saves image content, returns the id of its record.
+///
+/// Similar to `save_text` function.
+/// The image is stored in the `blobs` table, see `store_blob`.
+///
+/// # Arguments
+///
+/// * `conn` - The connection, or transaction, to save with.
+/// * `source` - The application the image has been copied from.
+/// * `png_bytes` - The PNG encoded image captured from the system clipboard.
+/// * `secrets` - The kinds of secrets found in the other representations of the clip.
+fn save_image(
+    conn: &Connection,
+    source: &ClipSource,
+    png_bytes: &[u8],
+    secrets: &[String],
+) -> Result<i64> {
+    let secrets = secrets_column(secrets);
+
+    // An image copied again only bumps its existing record
+    if let Some(id) = find_image(conn, png_bytes)? {
+        conn.execute(
+            "UPDATE history
+             SET timestamp = DATETIME('NOW', 'UTC'), last_copied_at = DATETIME('NOW', 'UTC'), copy_count = copy_count + 1, deleted_at = NULL, expires_at = NULL,
+                 source_app = ?1, icon_path = ?2, secrets = ?3
+             WHERE id = ?4",
+            params![source.app, source.icon_path, secrets, id],
+        )?;
+
+        return Ok(id);
+    }
+
+    let hash = store_blob(conn, png_bytes)?;
+    conn.execute(
+        "INSERT INTO history (source_app, icon_path, content_type, content, blob_hash, secrets, created_at, last_copied_at)
+         VALUES (?1, ?2, 'IMAGE', x'', ?3, ?4, DATETIME('NOW', 'UTC'), DATETIME('NOW', 'UTC'))",
+        params![source.app, source.icon_path, hash, secrets],
+    )?;
+
+    Ok(conn.last_insert_rowid())
+}
+
+/// Finds the record of an image, images are deduplicated by the fingerprint of their PNG encoding.
+fn find_image(conn: &Connection, png_bytes: &[u8]) -> Result<Option<i64>> {
+    Ok(conn
+        .query_row(
+            "SELECT id FROM history WHERE content_type = 'IMAGE' AND blob_hash = ?1",
+            params![cipher()?.fingerprint(png_bytes)],
+            |row| row.get(0),
+        )
+        .optional()?)
+}
+
+/// Stores an image once in the `blobs` table, returns its key.
+///
+/// The PNG encoded image is stored encrypted, keyed by its fingerprint,
+/// together with a thumbnail for previewing.
+/// Fails if the image cannot be decoded.
+fn store_blob(conn: &Connection, png_bytes: &[u8]) -> Result<String> {
+    let hash = cipher()?.fingerprint(png_bytes);
+    let exists = conn
+        .query_row("SELECT 1 FROM blobs WHERE hash = ?1", params![hash], |_| {
+            Ok(())
+        })
+        .optional()?
+        .is_some();
+
+    if !exists {
+        let thumbnail = thumbnail_png(&image::load_from_memory(png_bytes)?.to_rgba8())?;
+
+        conn.execute(
+            "INSERT INTO blobs (hash, data, thumbnail) VALUES (?1, ?2, ?3)",
+            params![
+                hash,
+                cipher()?.encrypt(png_bytes)?,
+                thumbnail
+                    .map(|thumbnail| cipher()?.encrypt(&thumbnail))
+                    .transpose()?
+            ],
+        )?;
+    }
+
+    Ok(hash)
+}
+
+/// Replaces the representations of a history record.
+///
+/// Contents are stored encrypted, images in the `blobs` table.
+fn replace_representations(
+    conn: &Connection,
+    id: i64,
+    representations: &[Representation],
+) -> Result<()> {
+    // Delete first, releasing a blob must not race with storing it again
+    conn.execute(
+        "DELETE FROM representations WHERE history_id = ?1",
+        params![id],
+    )?;
+
+    for representation in representations {
+        let (content, blob_hash) = match representation {
+            Representation::Image(png_bytes) => (Vec::new(), Some(store_blob(conn, png_bytes)?)),
+            _ => (cipher()?.encrypt(&representation.to_bytes())?, None),
+        };
+
+        conn.execute(
+            "INSERT OR REPLACE INTO representations (history_id, kind, content, blob_hash) VALUES (?1, ?2, ?3, ?4)",
+            params![id, representation.kind(), content, blob_hash],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Deletes the trashed records of a backup for good, overwriting their contents.
+fn purge_trash(path: &Path) -> Result<()> {
+    let conn = Connection::open(path)?;
+
+    conn.pragma_update(None, "secure_delete", true)?;
+    crypto::register_sql_functions(&conn)?;
+    if conn.execute("DELETE FROM history WHERE deleted_at IS NOT NULL", [])? > 0 {
+        conn.execute_batch("VACUUM")?;
+    }
+
+    Ok(())
+}
+
+/// Whether a database holds contents encrypted under an existing key, see `crypto::init`.
+fn has_encrypted_contents(conn: &Connection) -> Result<bool> {
+    let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+    // Contents are encrypted from schema version 8 on, see `migrations::v8_encrypt_contents`
+    if version < 8 {
+        return Ok(false);
+    }
+
+    Ok(conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM history) OR EXISTS (SELECT 1 FROM blobs)",
+        [],
+        |row| row.get(0),
+    )?)
+}
+
+/// Checks that a file is an intact clipboard database this version can open and decrypt, see `HistoryStore::restore`.
+///
+/// A sample content is decrypted, so a backup encrypted with another key is refused before it replaces the history.
+fn check_backup(path: &Path) -> Result<()> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+    let integrity: String = conn.query_row("PRAGMA integrity_check", [], |row| row.get(0))?;
+    if integrity != "ok" {
+        return Err(Error::InvalidBackup(format!("corrupted: {}", integrity)));
+    }
+
+    let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version > SCHEMA_VERSION {
+        return Err(Error::SchemaTooNew {
+            found: version,
+            supported: SCHEMA_VERSION,
+        });
+    }
+
+    conn.query_row("SELECT COUNT(*) FROM history", [], |row| {
+        row.get::<_, i64>(0)
+    })
+    .map_err(|_| Error::InvalidBackup("not a clipboard database".to_string()))?;
+
+    // Contents are encrypted from schema version 8 on, see `migrations::v8_encrypt_contents`
+    if version < 8 {
+        return Ok(());
+    }
+
+    let sample: Option<Vec<u8>> = conn
+        .query_row(
+            "SELECT content FROM history WHERE length(content) > 0
+             UNION ALL SELECT data FROM blobs
+             LIMIT 1",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(sealed) = sample {
+        cipher()?.decrypt(&sealed).map_err(|_| Error::WrongKey)?;
+    }
+
+    Ok(())
+}
+
+/// Builds an FTS5 query matching every word as a prefix.
+///
+/// Words are quoted so characters in the user input are never interpreted as FTS5 syntax.
+///
+/// # Example
+/// ```
+/// assert_eq!(fts_query(&["foo", "\"bar"]), r#""foo"* """bar"*"#);
+/// ```
+fn fts_query(words: &[&str]) -> String {
+    words
+        .iter()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Maps a raw database row to the `Item` struct.
+///
+/// # Arguments
+///
+/// * `row` - A reference to a single row result from a rusqlite query.
+fn row_to_item(row: &Row) -> rusqlite::Result<Item> {
+    let id: i64 = row.get(0)?;
+    let source_app: String = row.get(1)?;
+    let icon_path: String = row.get(2)?;
+    let content_type: String = row.get(3)?;
+    let content: ValueRef = row.get_ref(4)?;
+    let timestamp: String = row.get(5)?;
+    let pinned: bool = row.get(6)?;
+    let representation: ValueRef = row.get_ref(7)?;
+    let subtype: Option<String> = row.get(8)?;
+    let language: Option<String> = row.get(9)?;
+    let created_at: Option<String> = row.get(10)?;
+    let last_copied_at: Option<String> = row.get(11)?;
+    let last_pasted_at: Option<String> = row.get(12)?;
+    let copy_count: i64 = row.get(13)?;
+    let paste_count: i64 = row.get(14)?;
+    let secrets: Option<String> = row.get(15)?;
+
+    let content_type = match content_type.as_str() {
+        "IMAGE" => ContentTypes::Image,
+        "TEXT" => ContentTypes::Text,
+        "HTML" => ContentTypes::Html,
+        "FILES" => ContentTypes::Files,
+        // Written by a newer version, or corrupted
+        other => {
+            return Err(rusqlite::Error::FromSqlConversionFailure(
+                3,
+                Type::Text,
+                format!("unknown content type {:?}", other).into(),
+            ))
+        }
+    };
+
+    let content_raw_bytes = read_content(content, 4)?;
+
+    let content = match content_type {
+        ContentTypes::Image => general_purpose::STANDARD.encode(&content_raw_bytes),
+        ContentTypes::Text | ContentTypes::Html | ContentTypes::Files => {
+            String::from_utf8_lossy(&content_raw_bytes).to_string()
+        }
+    };
+
+    let representation = match representation {
+        ValueRef::Null => None,
+        representation => {
+            Representation::from_bytes(content_type.as_str(), read_content(representation, 7)?)
+        }
+    };
+    let html = match &representation {
+        Some(Representation::Html(html)) => Some(sanitize_html(html)),
+        _ => None,
+    };
+    let files = match representation {
+        Some(Representation::FileList(files)) => files,
+        _ => Vec::new(),
+    };
+    let subtype = subtype.and_then(|subtype| ContentSubtype::parse(&subtype, language));
+
+    let timestamp = parse_timestamp(&timestamp);
+    let created_at = created_at.as_deref().map_or(timestamp, parse_timestamp);
+    let last_copied_at = last_copied_at.as_deref().map_or(timestamp, parse_timestamp);
+    let last_pasted_at = last_pasted_at.as_deref().map(parse_timestamp);
+    let secrets = secrets
+        .map(|secrets| secrets.lines().map(str::to_string).collect())
+        .unwrap_or_default();
+
+    Ok(Item {
+        id,
+        source_app,
+        icon_path,
+        content_type,
+        content,
+        html,
+        files,
+        subtype,
+        timestamp,
+        created_at,
+        last_copied_at,
+        last_pasted_at,
+        copy_count,
+        paste_count,
+        secrets,
+        pinned,
+    })
+}
+
+/// The parameters of `FILTER_CONDITIONS`, in the order of their placeholders.
+fn filter_params(
+    filter: &RecordFilter,
+) -> (
+    Option<String>,
+    Option<String>,
+    Option<&str>,
+    Option<&'static str>,
+) {
+    (
+        filter.since.map(format_timestamp),
+        filter.until.map(format_timestamp),
+        filter.source_app.as_deref(),
+        filter.content_type.as_ref().map(ContentTypes::as_str),
+    )
+}
+
+/// Formats a timestamp as `DATETIME('NOW', 'UTC')` writes it, to the second.
+fn format_timestamp(timestamp: DateTime<Utc>) -> String {
+    timestamp.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// The `secrets` column of a record, one kind of secret per line, `NULL` for none.
+fn secrets_column(secrets: &[String]) -> Option<String> {
+    (!secrets.is_empty()).then(|| secrets.join("\n"))
+}
+
+/// Parses a timestamp written by `DATETIME('NOW', 'UTC')`, now if it is malformed.
+fn parse_timestamp(timestamp: &str) -> DateTime<Utc> {
+    NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S")
+        .map(|naive| Utc.from_utc_datetime(&naive))
+        .unwrap_or_else(|_| Utc::now())
+}
+
+/// Reads a content column, decrypting it.
+///
+/// Empty BLOBs (the `content` of image records) are returned as is.
+///
+/// # Arguments
+///
+/// * `value` - The raw column value.
+/// * `column` - The column index, for error reporting.
+fn read_content(value: ValueRef, column: usize) -> rusqlite::Result<Vec<u8>> {
+    match value {
+        ValueRef::Blob(sealed) if !sealed.is_empty() => {
+            let conversion_failure = |err: Box<dyn std::error::Error + Send + Sync>| {
+                rusqlite::Error::FromSqlConversionFailure(column, Type::Blob, err)
+            };
+
+            cipher()
+                .map_err(|err| conversion_failure(Box::new(err)))?
+                .decrypt(sealed)
+                .map_err(|err| conversion_failure(Box::new(err)))
+        }
+        ValueRef::Blob(bytes) => Ok(bytes.to_vec()),
+        ValueRef::Text(text) => Ok(text.to_vec()),
+        _ => Ok(Vec::new()),
+    }
+}
@@ -1,8 +1,15 @@
 use arboard::ImageData;
-use base64::engine::general_purpose;
-use base64::prelude::*;
 use chrono::{DateTime, Local, Utc};
-use std::borrow::Cow;
+use image::error::{ParameterError, ParameterErrorKind};
+use image::{imageops, GenericImageView, ImageBuffer, ImageError, ImageFormat, Rgba};
+use ring::digest;
+use std::io::Cursor;
+
+use crate::backend::error::Result;
+
+// Bounding box of image thumbnails, twice the size of a `ClipboardCard` for HiDPI displays
+const THUMBNAIL_WIDTH: u32 = 480;
+const THUMBNAIL_HEIGHT: u32 = 360;
 
 /// Converts a timestamp to a human-readable relative time string.
 ///
@@ -53,28 +60,393 @@ pub fn humanize_time(timestamp: DateTime<Utc>) -> String {
     local_ts.format("%Y-%m-%d").to_string()
 }
 
-/// Helper function to convert a Base64 string into `arboard::ImageData`.
-///
-/// This process involves:
-/// 1. Decoding the Base64 string into bytes.
-/// 2. Loading the image from memory (auto-detecting format like PNG, JPEG).
-/// 3. Converting the image to **RGBA8** format (required by system clipboards).
-/// 4. Extracting raw pixels and dimensions.
-///
-/// # Panics
-/// This function will **panic** if:
-/// - The input string is not valid Base64.
-/// - The decoded bytes do not represent a valid image.
-pub fn b64_to_img_data(content: &str) -> ImageData<'_> {
-    let image_bytes = general_purpose::STANDARD.decode(content).unwrap();
-    let dynamic_image = image::load_from_memory(&image_bytes).unwrap();
-    let rgba_image = dynamic_image.to_rgba8();
-    let (width, height) = rgba_image.dimensions();
-    let pixels = rgba_image.into_raw();
-
-    ImageData {
-        width: width as usize,
-        height: height as usize,
-        bytes: Cow::Owned(pixels),
+/// Converts a size in bytes to a human-readable string.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::utils::humanize_size;
+///
+/// println!("{}", humanize_size(1536)); // Output: 1.5 KB
+/// ```
+pub fn humanize_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KB", "MB", "GB", "TB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Helper function to encode `arboard::ImageData` as PNG.
+///
+/// `arboard` hands out raw **RGBA8** pixels, PNG keeps them lossless at a fraction of the size.
+///
+/// Fails if the pixels do not match the dimensions, or if the encoding fails.
+pub fn img_data_to_png(image: &ImageData) -> Result<Vec<u8>> {
+    let img_buffer = ImageBuffer::<Rgba<u8>, _>::from_raw(
+        image.width as u32,
+        image.height as u32,
+        image.bytes.as_ref(),
+    )
+    .ok_or_else(|| {
+        ImageError::Parameter(ParameterError::from_kind(
+            ParameterErrorKind::DimensionMismatch,
+        ))
+    })?;
+
+    let mut bytes: Vec<u8> = Vec::new();
+    img_buffer.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)?;
+
+    Ok(bytes)
+}
+
+/// Encodes a downscaled copy of an image as PNG, sized for a `ClipboardCard` on a HiDPI display.
+///
+/// The aspect ratio is preserved.
+/// Returns `None` if the image already fits the thumbnail size, fails if the encoding fails.
+pub fn thumbnail_png(image: &impl GenericImageView<Pixel = Rgba<u8>>) -> Result<Option<Vec<u8>>> {
+    let (width, height) = image.dimensions();
+
+    if width <= THUMBNAIL_WIDTH && height <= THUMBNAIL_HEIGHT {
+        return Ok(None);
+    }
+
+    let ratio = f64::min(
+        THUMBNAIL_WIDTH as f64 / width as f64,
+        THUMBNAIL_HEIGHT as f64 / height as f64,
+    );
+    let thumbnail = imageops::thumbnail(
+        image,
+        ((width as f64 * ratio).round() as u32).max(1),
+        ((height as f64 * ratio).round() as u32).max(1),
+    );
+
+    let mut bytes: Vec<u8> = Vec::new();
+    thumbnail.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)?;
+
+    Ok(Some(bytes))
+}
+
+/// Returns the lowercase hex encoded SHA-256 digest of `bytes`.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::utils::sha256_hex;
+///
+/// println!("{}", sha256_hex(b"")); // Output: e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855
+/// ```
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    digest::digest(&digest::SHA256, bytes)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+// Tags kept by `sanitize_html`, stripped of their attributes
+const ALLOWED_HTML_TAGS: &[&str] = &[
+    "a",
+    "b",
+    "blockquote",
+    "br",
+    "code",
+    "del",
+    "div",
+    "em",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "i",
+    "li",
+    "mark",
+    "ol",
+    "p",
+    "pre",
+    "s",
+    "span",
+    "strong",
+    "sub",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "th",
+    "thead",
+    "tr",
+    "u",
+    "ul",
+];
+// Tags whose content is never displayed
+const DROPPED_HTML_TAGS: &[&str] = &[
+    "head", "iframe", "math", "noscript", "object", "script", "select", "style", "svg", "template",
+    "textarea", "title",
+];
+// Tags ending a line in `html_to_text`
+const BLOCK_HTML_TAGS: &[&str] = &[
+    "blockquote",
+    "br",
+    "div",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "li",
+    "p",
+    "pre",
+    "tr",
+];
+
+/// Reduces HTML to a safe subset for previewing.
+///
+/// Only basic formatting tags are kept, all of their attributes are removed,
+/// so neither scripts, styles nor remote resources survive.
+/// Scripts, styles and the like are removed together with their content, other tags are unwrapped.
+/// `<` and `>` left in the text, e.g. of an unterminated tag, are escaped.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::utils::sanitize_html;
+///
+/// let html = r#"<p style="color: red" onclick="alert(1)">Hello <b>World</b><script>alert(2)</script></p>"#;
+/// assert_eq!(sanitize_html(html), "<p>Hello <b>World</b></p>");
+/// ```
+pub fn sanitize_html(html: &str) -> String {
+    let mut sanitized = String::with_capacity(html.len());
+
+    walk_html(html, |token| match token {
+        HtmlToken::Tag { name, closing } => {
+            if ALLOWED_HTML_TAGS.contains(&name) {
+                sanitized.push_str(if closing { "</" } else { "<" });
+                sanitized.push_str(name);
+                sanitized.push('>');
+            }
+        }
+        HtmlToken::Text(text) => {
+            sanitized.push_str(&text.replace('<', "&lt;").replace('>', "&gt;"))
+        }
+    });
+
+    sanitized
+}
+
+/// Extracts the text of an HTML document, used as the plain text fallback of HTML clips.
+///
+/// Block elements end a line, numeric and the common named character entities are decoded.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::utils::html_to_text;
+///
+/// assert_eq!(html_to_text("<p>Fish &amp; Chips</p><p>Tea</p>"), "Fish & Chips\nTea");
+/// ```
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+
+    walk_html(html, |token| match token {
+        HtmlToken::Tag { name, .. } => {
+            if BLOCK_HTML_TAGS.contains(&name) && !text.is_empty() && !text.ends_with('\n') {
+                text.push('\n');
+            }
+        }
+        HtmlToken::Text(chunk) => text.push_str(&decode_html_entities(chunk)),
+    });
+
+    text.trim().to_string()
+}
+
+enum HtmlToken<'a> {
+    /// A tag, its name in lowercase.
+    Tag { name: &'a str, closing: bool },
+    /// The text between tags as is, entities included, or the rest of the HTML after an unterminated tag.
+    Text(&'a str),
+}
+
+/// Splits HTML into tags and text, skipping comments, doctypes and the content of `DROPPED_HTML_TAGS`.
+fn walk_html(html: &str, mut on_token: impl FnMut(HtmlToken)) {
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        on_token(HtmlToken::Text(&rest[..start]));
+        rest = &rest[start..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+
+        // The end of the tag, skipping `>` inside quoted attribute values
+        let mut quote = None;
+        let end = rest
+            .char_indices()
+            .skip(1)
+            .find_map(|(i, c)| match (quote, c) {
+                (None, '"' | '\'') => {
+                    quote = Some(c);
+                    None
+                }
+                (Some(q), c) if q == c => {
+                    quote = None;
+                    None
+                }
+                (None, '>') => Some(i),
+                _ => None,
+            });
+        let Some(end) = end else {
+            break;
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        // `<!DOCTYPE>`, `<?xml?>` and malformed tags
+        if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            continue;
+        }
+
+        if DROPPED_HTML_TAGS.contains(&name.as_str()) {
+            if !closing && !tag.ends_with('/') {
+                let closing_tag = format!("</{}", name);
+                rest = rest
+                    .to_ascii_lowercase()
+                    .find(&closing_tag)
+                    .and_then(|close| rest[close..].find('>').map(|end| &rest[close + end + 1..]))
+                    .unwrap_or("");
+            }
+            continue;
+        }
+
+        on_token(HtmlToken::Tag {
+            name: &name,
+            closing,
+        });
+    }
+
+    on_token(HtmlToken::Text(rest));
+}
+
+fn decode_html_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+
+    decode_character_references(
+        &text
+            .replace("&nbsp;", " ")
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'"),
+    )
+}
+
+/// Decodes `&amp;`, `&#65;` and `&#x41;` in one pass, so `&#38;lt;` becomes `&lt;`, not `<`.
+fn decode_character_references(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let reference = rest[1..].split_once(';').and_then(|(name, _)| {
+            let c = match name.strip_prefix('#') {
+                _ if name == "amp" => Some('&'),
+                Some(hex) if hex.starts_with(['x', 'X']) => u32::from_str_radix(&hex[1..], 16)
+                    .ok()
+                    .and_then(char::from_u32),
+                Some(decimal) => decimal.parse::<u32>().ok().and_then(char::from_u32),
+                None => None,
+            }?;
+            Some((c, name.len() + 2))
+        });
+
+        match reference {
+            Some((c, len)) => {
+                decoded.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitizes_html() {
+        assert_eq!(
+            sanitize_html(
+                r#"<p style="color: red">Fish &amp; <b>Chips</b><script>alert(1)</script></p>"#
+            ),
+            "<p>Fish &amp; <b>Chips</b></p>"
+        );
+        assert_eq!(
+            sanitize_html(r#"<a href="javascript:alert(1)" title="a > b">link</a>"#),
+            "<a>link</a>"
+        );
+        assert_eq!(
+            sanitize_html("<!-- <img src=x onerror=alert(1)> -->ok"),
+            "ok"
+        );
+    }
+
+    #[test]
+    fn escapes_unterminated_tags() {
+        assert_eq!(
+            sanitize_html("<b x'y><img src=x onerror=alert(1)>"),
+            "&lt;b x'y&gt;&lt;img src=x onerror=alert(1)&gt;"
+        );
+        assert_eq!(
+            sanitize_html("<p>hi<img src=x onerror=alert(3)"),
+            "<p>hi&lt;img src=x onerror=alert(3)"
+        );
+    }
+
+    #[test]
+    fn extracts_text_from_html() {
+        assert_eq!(
+            html_to_text("<h1>Menu</h1><ul><li>Fish &amp; Chips</li><li>Tea</li></ul>"),
+            "Menu\nFish & Chips\nTea"
+        );
+        assert_eq!(html_to_text("&#65;&#x42;&#38;lt;"), "AB&lt;");
+        assert_eq!(
+            html_to_text("<style>p { color: red }</style>Hello"),
+            "Hello"
+        );
     }
 }
@@ -1,7 +1,7 @@
 mod backend;
 
-use arboard::Clipboard;
-use dioxus::html::{input_data::keyboard_types::Key};
+use chrono::{TimeDelta, Utc};
+use dioxus::html::input_data::keyboard_types::Key;
 use dioxus::prelude::*;
 use dioxus_desktop::{
     tao::dpi::{LogicalPosition, LogicalSize},
@@ -9,19 +9,37 @@ use dioxus_desktop::{
 };
 use global_hotkey::HotKeyState;
 use once_cell::sync::Lazy;
-use std::process::Command;
+use std::collections::HashMap;
+use std::iter;
 use std::sync::{Arc, RwLock};
 use std::thread;
-use std::{collections::HashMap, sync::atomic::Ordering};
+use std::time::Duration;
 use tokio::sync::mpsc;
 
-use crate::backend::clipboard::{self, ContentTypes};
-use crate::backend::clipboard::{update_timestamp, IS_INTERNAL_PASTE};
-use crate::backend::utils::{b64_to_img_data, humanize_time};
+use crate::backend::archive;
+use crate::backend::backup::{self, Backup};
+use crate::backend::classifier::{self, ContentSubtype};
+use crate::backend::clipboard::{self, CaptureState, ContentTypes, Cursor, Pinboard};
+use crate::backend::error::Error;
+use crate::backend::macos::{hide_frontmost_app, show_alert};
+use crate::backend::settings::{self, Settings};
+use crate::backend::store::{self, HistoryStore, RecordFilter};
+use crate::backend::utils::{humanize_size, humanize_time};
 
 const MAIN_CSS: Asset = asset!("/assets/main.css");
 const TAILWIND_CSS: Asset = asset!("/assets/tailwind.css");
 
+// Number of clipboard items fetched from the database at a time
+const PAGE_SIZE: i64 = 50;
+// Load the next page when the list is scrolled within this distance (in px) of its end
+const LOAD_MORE_THRESHOLD: f64 = 600.0;
+// Load the next page when the selection moves within this many items of the end
+const LOAD_MORE_AHEAD: usize = 5;
+// How long a toast stays on screen
+const TOAST_DURATION: Duration = Duration::from_secs(5);
+// How often the capture indicator is refreshed, e.g. to count down a timed pause
+const CAPTURE_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
 #[derive(Clone)]
 pub struct WindowInfo {
     pub is_visible: bool, // represents the current window's status is visible or not
@@ -31,14 +49,78 @@ pub struct WindowInfo {
 static WINDOW_REGISTRY: Lazy<Arc<RwLock<HashMap<String, WindowInfo>>>> =
     Lazy::new(|| Arc::new(RwLock::new(HashMap::new())));
 
+/// A message shown at the bottom of the `Paste` window for `TOAST_DURATION`.
+#[derive(Clone, Debug, PartialEq)]
+struct Toast {
+    id: u64,
+    message: String,
+    kind: ToastKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum ToastKind {
+    Error,
+    /// Records have been moved to the trash, the toast offers to restore them.
+    Undo(Vec<i64>),
+}
+
+/// The context menu of a `ClipboardCard`, at the position of the right click.
+#[derive(Clone, Debug, PartialEq)]
+struct ContextMenu {
+    item: clipboard::Item,
+    x: f64,
+    y: f64,
+}
+
 // ------------------------------------------------------------------
 //                            MAIN ENTRY
 // ------------------------------------------------------------------
 fn main() {
     let config = Config::new().with_window(default_app_window_config());
+    let store = match store::open_store() {
+        Ok(store) => store,
+        Err(err) => {
+            let message = format!("Failed to open the clipboard database: {}", err);
+            log::error!("{}", message);
+            eprintln!("{}", message);
+
+            // Launched from Finder there is no terminal to read the error in
+            if let Err(err) = show_alert("Paste-Fork cannot start", &message) {
+                log::error!("Failed to show the startup error: {}", err);
+            }
+            std::process::exit(1);
+        }
+    };
+
+    // An unreadable settings file is reported, the defaults apply
+    let settings = settings::load_settings().unwrap_or_else(|err| {
+        log::error!("Failed to load the settings, using the defaults: {}", err);
+        eprintln!("Failed to load the settings, using the defaults: {}", err);
+        Settings::default()
+    });
+    backup::set_backup_policy(settings.backup_policy());
+    clipboard::set_concealed_clips(settings.concealed_clips.clone());
+    if let Err(err) = clipboard::set_retention_policy(store.as_ref(), settings.retention_policy()) {
+        log::error!("Failed to enforce the retention policy: {}", err);
+    }
+
+    // Headless runs: `--export` / `--import` / `--backup` / `--restore` exit once done
+    if let Some(result) =
+        archive::run_from_args(store.as_ref()).or_else(|| backup::run_from_args(store.as_ref()))
+    {
+        match result {
+            Ok(summary) => println!("{}", summary),
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
 
     dioxus::LaunchBuilder::desktop()
         .with_cfg(config)
+        .with_context(store)
         .launch(App);
 }
 
@@ -75,11 +157,13 @@ fn default_paste_window_config() -> WindowBuilder {
 #[component]
 fn App() -> Element {
     let window = use_window();
+    let store = use_context::<Arc<dyn HistoryStore>>();
 
     // Initialize child windows
+    // Child windows run their own `VirtualDom`, the store is handed over as its root context
     use_effect(move || {
         window.new_window(
-            VirtualDom::new(Paste),
+            VirtualDom::new(Paste).with_root_context(store.clone()),
             Config::new().with_window(default_paste_window_config()),
         );
     });
@@ -96,15 +180,75 @@ fn App() -> Element {
     })
     .unwrap();
 
+    // Hotkey: A toggle for pausing capture until resumed, e.g. to copy sensitive material
+    use_global_shortcut("ALT+SHIFT+CMD+V", move |s| {
+        if let HotKeyState::Pressed = s {
+            if clipboard::capture_state() == CaptureState::Recording {
+                clipboard::pause_capture(None);
+            } else {
+                clipboard::resume_capture();
+            }
+        }
+    })
+    .unwrap();
+
     rsx!("")
 }
 
 #[component]
 fn Paste() -> Element {
     let window = use_window();
+    // The history store, shared with the clipboard listener
+    let store = use_hook(|| CopyValue::new(consume_context::<Arc<dyn HistoryStore>>()));
     let mut clipboard_items = use_signal(Vec::<clipboard::Item>::new);
+    let mut pinned_items = use_signal(Vec::<clipboard::Item>::new);
+    let mut query_results = use_signal(Vec::<clipboard::Item>::new); // The active pinboard or the search results
+    let mut pinboards = use_signal(Vec::<Pinboard>::new);
+    let mut active_pinboard = use_signal(|| None::<i64>); // `None` shows the whole history
+    let mut new_pinboard_name = use_signal(|| None::<String>); // `Some` while naming a new pinboard
+    let mut total_items = use_signal(|| 0_i64);
+    let mut has_more_items = use_signal(|| false);
     let mut search_bar = use_signal(|| "".to_string());
     let mut selected_item_index = use_signal(|| 0);
+    let mut backups = use_signal(Vec::<Backup>::new);
+    let mut toasts = use_signal(Vec::<Toast>::new);
+    let mut next_toast_id = use_signal(|| 0_u64);
+    let mut context_menu = use_signal(|| None::<ContextMenu>);
+    let mut trashed_batches = use_signal(Vec::<Vec<i64>>::new); // Undone last first with ⌘Z
+    let mut capture_indicator = use_signal(|| capture_label(clipboard::capture_state())); // `Some` while paused
+
+    // A hook to keep the capture indicator up to date
+    // Capture is also toggled by a hotkey, and timed pauses end on their own
+    use_hook(|| {
+        spawn(async move {
+            loop {
+                let indicator = capture_label(clipboard::capture_state());
+                if *capture_indicator.peek() != indicator {
+                    capture_indicator.set(indicator);
+                }
+                tokio::time::sleep(CAPTURE_REFRESH_INTERVAL).await;
+            }
+        })
+    });
+
+    // A callback to show a toast for `TOAST_DURATION`
+    let show_toast = use_callback(move |(message, kind): (String, ToastKind)| {
+        let id = *next_toast_id.peek();
+        next_toast_id.set(id + 1);
+        toasts.write().push(Toast { id, message, kind });
+
+        spawn(async move {
+            tokio::time::sleep(TOAST_DURATION).await;
+            toasts.write().retain(|toast| toast.id != id);
+        });
+    });
+
+    // A callback to surface a failure as a toast
+    // Failures are never fatal, the window keeps showing what it has loaded
+    let report_error = use_callback(move |message: String| {
+        log::error!("{}", message);
+        show_toast.call((message, ToastKind::Error));
+    });
 
     // Change Window Size
     use_effect({
@@ -124,28 +268,270 @@ fn Paste() -> Element {
         }
     });
 
-    // A hook to filter the clipboard items based on the user input
-    // The search bar is used to filter the clipboard items
-    let filtered_items = use_memo(move || {
-        let query = search_bar.read().to_lowercase();
-        let clipboard_items = clipboard_items.read();
+    // A hook to query the items of the active pinboard or matching the user input
+    // Only a few pages of items are loaded, so searching is delegated to the database
+    // An effect rather than part of `filtered_items`, a memo must not report failures by writing the toasts
+    use_effect(move || {
+        let query = search_bar.read().clone();
+        // Queried again whenever the history is reloaded, e.g. after deleting an item
+        clipboard_items.read();
+        pinned_items.read();
 
-        if query.is_empty() {
-            log::trace!("Query is empty");
-            clipboard_items.clone()
+        let results = if let Some(pinboard_id) = *active_pinboard.read() {
+            // Pinboards are small, they are loaded and searched as a whole
+            let query = query.trim().to_lowercase();
+            store
+                .read()
+                .get_pinboard_records(pinboard_id)
+                .map(clipboard::refresh_files)
+                .unwrap_or_else(|err| {
+                    report_error.call(format!("Failed to load the pinboard: {}", err));
+                    Vec::new()
+                })
+                .into_iter()
+                .filter(|item| {
+                    query.is_empty()
+                        || item.source_app.to_lowercase().contains(&query)
+                        || (item.content_type != ContentTypes::Image
+                            && item.content.to_lowercase().contains(&query))
+                })
+                .collect()
+        } else if query.trim().is_empty() {
+            Vec::new()
         } else {
             log::trace!("User input: {}", query);
-            clipboard_items
+            let search = store.read().search_text(&query);
+            let mut items = search.map(clipboard::refresh_files).unwrap_or_else(|err| {
+                report_error.call(format!("Failed to search: {}", err));
+                Vec::new()
+            });
+            items.sort_by_key(|item| !item.pinned);
+            items
+        };
+
+        query_results.set(results);
+    });
+
+    // A hook to filter the clipboard items based on the user input
+    // Pinned items always come first
+    // When a pinboard is active, only its items are listed
+    let filtered_items = use_memo(move || {
+        if active_pinboard.read().is_some() || !search_bar.read().trim().is_empty() {
+            query_results.read().clone()
+        } else {
+            log::trace!("Query is empty");
+            pinned_items
+                .read()
                 .iter()
-                .filter(|item| {
-                    item.source_app.to_lowercase().contains(&query)
-                        || item.content.to_lowercase().contains(&query)
-                })
+                .chain(clipboard_items.read().iter())
                 .cloned()
                 .collect()
         }
     });
 
+    // Keep the selection on the list when it shrinks, e.g. after deleting its last item
+    use_effect(move || {
+        let len = filtered_items.read().len();
+        if *selected_item_index.peek() >= len {
+            selected_item_index.set(len.saturating_sub(1));
+        }
+    });
+
+    // The number of leading pinned items in `filtered_items`
+    let pinned_count = use_memo(move || {
+        filtered_items
+            .read()
+            .iter()
+            .take_while(|item| item.pinned)
+            .count()
+    });
+
+    // A callback to append the next page of clipboard items
+    // Triggered when the list is scrolled or navigated near its end
+    let load_more = use_callback(move |_: ()| {
+        if !*has_more_items.peek()
+            || !search_bar.peek().trim().is_empty()
+            || active_pinboard.peek().is_some()
+        {
+            return;
+        }
+
+        let cursor = clipboard_items.peek().last().map(Cursor::from);
+        let page = store.read().get_records_page(cursor, PAGE_SIZE);
+        let page = match page {
+            Ok(page) => clipboard::refresh_files(page),
+            Err(err) => {
+                report_error.call(format!("Failed to load more items: {}", err));
+                return;
+            }
+        };
+
+        log::trace!("Loaded {} more clipboard items", page.len());
+        has_more_items.set(page.len() as i64 == PAGE_SIZE);
+        clipboard_items.write().extend(page);
+    });
+
+    // A callback to reload the loaded clipboard items from the database
+    // Reloads as many items as currently loaded, so the list does not shrink while scrolled
+    let reload_items = use_callback(move |_: ()| {
+        let loaded = (clipboard_items.peek().len() as i64).max(PAGE_SIZE);
+        let store = store.read();
+        // Everything is loaded before anything is replaced, a failure leaves the list as is
+        let history = store.get_records_page(None, loaded).and_then(|items| {
+            Ok((
+                items,
+                store.count_records()?,
+                store.get_pinned_records()?,
+                store.get_pinboards()?,
+            ))
+        });
+
+        match history {
+            Ok((items, count, pinned, all_pinboards)) => {
+                has_more_items.set(items.len() as i64 == loaded);
+                total_items.set(count);
+                pinned_items.set(clipboard::refresh_files(pinned));
+                pinboards.set(all_pinboards);
+                clipboard_items.set(clipboard::refresh_files(items));
+            }
+            Err(err) => report_error.call(format!("Failed to load the clipboard history: {}", err)),
+        }
+
+        // Backups are listed for restoring, not listing them is not worth a toast
+        match backup::list_backups() {
+            Ok(all_backups) => backups.set(all_backups),
+            Err(err) => log::warn!("Failed to list backups: {}", err),
+        }
+    });
+
+    // Action Handler `restore_backup`: Replace the history with a backup and reload everything
+    let restore_backup =
+        use_callback(move |path: std::path::PathBuf| {
+            match backup::restore_backup(store.read().as_ref(), &path) {
+                Ok(()) => {
+                    active_pinboard.set(None);
+                    selected_item_index.set(0);
+                    reload_items.call(());
+                }
+                Err(Error::WrongKey) => report_error.call(
+                    "The backup is encrypted with another key, set PASTE_FORK_KEY_FILE to its key to restore it".to_string(),
+                ),
+                Err(Error::SchemaTooNew { .. }) => report_error.call(
+                    "The backup was made by a newer version of Paste-Fork, update to restore it".to_string(),
+                ),
+                Err(Error::InvalidBackup(reason)) => {
+                    report_error.call(format!("The backup cannot be restored, it is {}", reason))
+                }
+                Err(err) => report_error.call(format!("Failed to restore the backup: {}", err)),
+            }
+        });
+
+    // A callback to switch to the next (`1`) or previous (`-1`) pinboard
+    // The whole history comes before the first pinboard
+    let cycle_pinboard = use_callback(move |step: isize| {
+        let views: Vec<Option<i64>> = iter::once(None)
+            .chain(pinboards.peek().iter().map(|pinboard| Some(pinboard.id)))
+            .collect();
+        let current = views
+            .iter()
+            .position(|view| *view == *active_pinboard.peek())
+            .unwrap_or(0);
+        let next = (current as isize + step).rem_euclid(views.len() as isize) as usize;
+
+        active_pinboard.set(views[next]);
+        selected_item_index.set(0);
+    });
+
+    // Action Handler `create_pinboard`: Create a pinboard named after the user input and switch to it
+    let create_pinboard = use_callback(move |name: String| {
+        new_pinboard_name.set(None);
+
+        if name.trim().is_empty() {
+            return;
+        }
+
+        match store.read().create_pinboard(name.trim()) {
+            Ok(pinboard_id) => {
+                reload_items.call(());
+                active_pinboard.set(Some(pinboard_id));
+                selected_item_index.set(0);
+            }
+            Err(err) => report_error.call(format!("Failed to create pinboard {:?}: {}", name, err)),
+        }
+    });
+
+    // Action Handler `toggle_pin`: Pin or unpin a clipboard item
+    let toggle_pin = use_callback(move |item: clipboard::Item| {
+        let result = if item.pinned {
+            store.read().unpin(item.id)
+        } else {
+            store.read().pin(item.id)
+        };
+
+        match result {
+            Ok(()) => reload_items.call(()),
+            Err(err) => report_error.call(format!("Failed to pin the item: {}", err)),
+        }
+    });
+
+    // A callback to report records moved to the trash, they can be restored from the toast or with ⌘Z
+    // Without a trash period, they are deleted for good at once
+    let on_trashed = use_callback(move |ids: Vec<i64>| {
+        let policy = clipboard::retention_policy();
+        let is_purged = policy.trash_period.is_zero();
+
+        if is_purged {
+            if let Err(err) = store.read().enforce_retention(&policy) {
+                report_error.call(format!("Failed to delete the items: {}", err));
+            }
+        }
+        reload_items.call(());
+
+        if ids.is_empty() || is_purged {
+            return;
+        }
+
+        let message = match ids.len() {
+            1 => "Deleted 1 item".to_string(),
+            n => format!("Deleted {} items", n),
+        };
+        trashed_batches.write().push(ids.clone());
+        show_toast.call((message, ToastKind::Undo(ids)));
+    });
+
+    // Action Handler `delete_item`: Move a clipboard item to the trash
+    let delete_item =
+        use_callback(
+            move |item: clipboard::Item| match store.read().trash_records(&[item.id]) {
+                Ok(_) => on_trashed.call(vec![item.id]),
+                Err(err) => report_error.call(format!("Failed to delete the item: {}", err)),
+            },
+        );
+
+    // Action Handler `delete_matching`: Move the clipboard items matching a filter to the trash
+    // Pinned items and items on a pinboard are kept
+    let delete_matching =
+        use_callback(
+            move |filter: RecordFilter| match store.read().trash_matching(&filter) {
+                Ok(ids) => on_trashed.call(ids),
+                Err(err) => report_error.call(format!("Failed to delete the items: {}", err)),
+            },
+        );
+
+    // Action Handler `undo_delete`: Restore clipboard items from the trash
+    let undo_delete = use_callback(move |ids: Vec<i64>| {
+        trashed_batches.write().retain(|batch| *batch != ids);
+        toasts
+            .write()
+            .retain(|toast| toast.kind != ToastKind::Undo(ids.clone()));
+
+        match store.read().untrash_records(&ids) {
+            Ok(0) => report_error.call("The deleted items are gone for good".to_string()),
+            Ok(_) => reload_items.call(()),
+            Err(err) => report_error.call(format!("Failed to restore the items: {}", err)),
+        }
+    });
+
     // A hook to set the visibility of the `Paste` window
     // A unbounded channel has been used to toggle the visibility of the `Paste` window
     let visibility_setter = use_hook(|| {
@@ -181,15 +567,36 @@ fn Paste() -> Element {
         tx
     });
 
-    // Start listening to system clipboard after component rendered
+    // Start listening to system clipboard, enforcing the retention policy and backing up after component rendered
     use_effect(move || {
-        let (tx, mut rx) = mpsc::unbounded_channel::<()>();
-        thread::spawn(move || clipboard::listen(tx));
+        let (tx, mut rx) = mpsc::unbounded_channel::<backend::error::Result<()>>();
+        let retention_tx = tx.clone();
+        let backup_tx = tx.clone();
+        let listener_error_tx = tx.clone();
+        let listener_store = store.read().clone();
+        let retention_store = store.read().clone();
+        let backup_store = store.read().clone();
+        thread::spawn(move || {
+            if let Err(err) = clipboard::listen(listener_store, tx) {
+                log::error!("Failed to listen to the clipboard: {}", err);
+                let _ = listener_error_tx.send(Err(err));
+            }
+        });
+        thread::spawn(move || clipboard::run_retention_timer(retention_store, retention_tx));
+        thread::spawn(move || backup::run_backup_timer(backup_store, backup_tx));
+
+        reload_items.call(());
 
         spawn(async move {
-            while rx.recv().await.is_some() {
-                log::trace!("Received clipboard DB completed updating signal");
-                clipboard_items.set(clipboard::get_all_records().unwrap()); // BUG: Memory could goes insufficient if `get_all_records` returns massive amount of data
+            while let Some(result) = rx.recv().await {
+                match result {
+                    Ok(()) => {
+                        log::trace!("Received clipboard DB completed updating signal");
+                        reload_items.call(());
+                    }
+                    Err(err) => report_error
+                        .call(format!("Failed to update the clipboard history: {}", err)),
+                }
             }
         });
     });
@@ -219,27 +626,42 @@ fn Paste() -> Element {
 
         move |item: clipboard::Item| {
             spawn(async move {
-                // BE Update: update system clipboard
-                let mut clipboard = Clipboard::new().unwrap();
-
-                IS_INTERNAL_PASTE.store(true, Ordering::SeqCst);
+                // BE Update: update system clipboard with every representation of the item
+                // `item.content` only holds the primary representation, with a thumbnail of images
+                // Nothing else is updated if the item cannot be pasted, the window stays open
+                let representations = match store.read().get_representations(item.id) {
+                    Ok(representations) => representations,
+                    Err(err) => {
+                        report_error.call(format!("Failed to load the item: {}", err));
+                        return;
+                    }
+                };
 
-                if item.content_type == ContentTypes::Text {
-                    clipboard.set_text(&item.content).unwrap();
-                } else {
-                    clipboard.set_image(b64_to_img_data(&item.content)).unwrap();
+                // The clipboard listener skips this change, the paste is recorded below instead
+                if let Err(err) = clipboard::write_representations(&representations) {
+                    report_error.call(format!("Failed to paste the item: {}", err));
+                    return;
                 }
 
                 // DB Update: Update the selected item's timestamp to now
-                update_timestamp(item.id).unwrap();
-
-                // UI Update: Move the selected item to the index[0]
-                let mut clipboard_items = clipboard_items.write();
-                if let Some(pos) = clipboard_items.iter().position(|i| i.id == item.id) {
-                    let item = clipboard_items.remove(pos);
-                    clipboard_items.insert(0, item);
+                if let Err(err) = store.read().record_paste(item.id) {
+                    report_error.call(format!("Failed to move the item to the top: {}", err));
                 }
 
+                // UI Update: Move the selected item to the index[0] of its section
+                // The item may not be loaded yet if it has been found by searching
+                let mut section_items = if item.pinned {
+                    pinned_items.write()
+                } else {
+                    clipboard_items.write()
+                };
+                let item = match section_items.iter().position(|i| i.id == item.id) {
+                    Some(pos) => section_items.remove(pos),
+                    None => item,
+                };
+                section_items.insert(0, item);
+                drop(section_items);
+
                 // UI Update: Reset the search bar and selected index
                 search_bar.set("".to_string());
                 selected_item_index.set(0);
@@ -248,23 +670,17 @@ fn Paste() -> Element {
                 visibility_setter.send(false).unwrap();
 
                 // UX Update: refocusing preview application
-                Command::new("osascript")
-                    .arg("-e")
-                    .arg(
-                        r#"
-                        tell application "System Events"
-                            set visible of first process whose frontmost is true to false
-                        end tell
-                    "#,
-                    )
-                    .output()
-                    .unwrap();
+                if let Err(err) = hide_frontmost_app() {
+                    report_error.call(format!(
+                        "Failed to refocus the previous application: {}",
+                        err
+                    ));
+                }
 
                 // TODO UX Update: Automatically pasting
                 // Currently not supported.
                 // Pasting immediately after user selection would require integration with macOS system APIs.
             });
-            
         }
     };
 
@@ -278,6 +694,35 @@ fn Paste() -> Element {
             let max_len = filtered_items.read().len();
             let filtered_items = filtered_items.read();
 
+            // Tab: Switch between pinboards, also available when the list is empty
+            if evt.key() == Key::Tab {
+                evt.prevent_default();
+                cycle_pinboard.call(if evt.modifiers().contains(Modifiers::SHIFT) {
+                    -1
+                } else {
+                    1
+                });
+                return;
+            }
+
+            // ⌘Z: Restore the last deleted items, else leave the key to the search bar
+            if evt.key() == Key::Character("z".to_string())
+                && evt.modifiers().contains(Modifiers::META)
+            {
+                let last_batch = trashed_batches.peek().last().cloned();
+                if let Some(ids) = last_batch {
+                    evt.prevent_default();
+                    undo_delete.call(ids);
+                }
+                return;
+            }
+
+            // Escape: Close the context menu before the window
+            if evt.key() == Key::Escape && context_menu.peek().is_some() {
+                context_menu.set(None);
+                return;
+            }
+
             if max_len == 0 {
                 return;
             }
@@ -286,6 +731,10 @@ fn Paste() -> Element {
                 Key::ArrowRight => {
                     let current_idx = *selected_item_index.read();
                     selected_item_index.set((current_idx + 1) % max_len);
+
+                    if current_idx + 1 + LOAD_MORE_AHEAD >= max_len {
+                        load_more.call(());
+                    }
                 }
                 Key::ArrowLeft => {
                     let current_idx = *selected_item_index.read();
@@ -306,6 +755,10 @@ fn Paste() -> Element {
                             if let Some(item) = filtered_items.get(idx) {
                                 do_paste(item.clone());
                             }
+                        } else if c == "p" {
+                            if let Some(item) = filtered_items.get(*selected_item_index.read()) {
+                                toggle_pin.call(item.clone());
+                            }
                         }
                     }
                 }
@@ -315,6 +768,12 @@ fn Paste() -> Element {
                     }
                     visibility_setter.send(false).unwrap();
                 }
+                Key::Backspace if evt.modifiers().contains(Modifiers::META) => {
+                    evt.prevent_default();
+                    if let Some(item) = filtered_items.get(*selected_item_index.read()) {
+                        delete_item.call(item.clone());
+                    }
+                }
                 Key::Escape => {
                     visibility_setter.send(false).unwrap();
                 }
@@ -323,15 +782,65 @@ fn Paste() -> Element {
         }
     };
 
+    // Renders the `ClipboardCard` of an enumerated item of `filtered_items`
+    let render_card = {
+        to_owned![do_paste];
+
+        move |(index, item): (usize, &clipboard::Item)| {
+            to_owned![do_paste, item];
+            let item_id = item.id;
+            let menu_item = item.clone();
+
+            rsx! {
+                ClipboardCard {
+                    key: "{item.id}",
+                    index: index,
+                    is_selected: index == *selected_item_index.read(),
+                    item: item.clone(),
+                    on_click: move |_| {
+                        to_owned![do_paste];
+                        if index == *selected_item_index.read() {
+                            do_paste(item.clone());
+                        } else {
+                            selected_item_index.set(index);
+                        }
+                    },
+                    on_toggle_pin: move |item| toggle_pin.call(item),
+                    on_context_menu: move |(x, y)| {
+                        selected_item_index.set(index);
+                        context_menu.set(Some(ContextMenu { item: menu_item.clone(), x, y }));
+                    },
+                    pinboards: pinboards.read().clone(),
+                    in_pinboard: active_pinboard.read().is_some(),
+                    on_add_to_pinboard: move |pinboard_id| {
+                        match store.read().add_to_pinboard(pinboard_id, item_id) {
+                            Ok(()) => reload_items.call(()),
+                            Err(err) => report_error.call(format!("Failed to add the item to the pinboard: {}", err)),
+                        }
+                    },
+                    on_remove_from_pinboard: move |_| {
+                        if let Some(pinboard_id) = *active_pinboard.peek() {
+                            match store.read().remove_from_pinboard(pinboard_id, item_id) {
+                                Ok(()) => reload_items.call(()),
+                                Err(err) => report_error.call(format!("Failed to remove the item from the pinboard: {}", err)),
+                            }
+                        }
+                    },
+                }
+            }
+        }
+    };
+
     rsx! {
         document::Link { rel: "stylesheet", href: MAIN_CSS }
         document::Link { rel: "stylesheet", href: TAILWIND_CSS }
         div {
             class: "fixed inset-0 w-full h-full bg-transparent flex items-center justify-center p-3",
             onkeydown: handle_keydown,
+            onclick: move |_| context_menu.set(None),
 
             div {
-                class: "w-full h-full bg-[#252526] text-white flex flex-col overflow-hidden rounded-2xl shadow-2xl border border-white/10 relative",
+                class: if capture_indicator.read().is_some() { "w-full h-full bg-[#252526] text-white flex flex-col overflow-hidden rounded-2xl shadow-2xl border-2 border-amber-500 relative" } else { "w-full h-full bg-[#252526] text-white flex flex-col overflow-hidden rounded-2xl shadow-2xl border border-white/10 relative" },
 
                 // Header (Search Bar, Item Count)
                 div {
@@ -339,42 +848,165 @@ fn Paste() -> Element {
                     div { class: "mr-3 text-2xl", "🔍" }
                     input {
                         class: "flex-1 bg-transparent border-none outline-none text-xl text-white placeholder-gray-500 font-light",
-                        placeholder: "Type to search...",
+                        placeholder: "Type to search... (is:url, is:code, is:rust)",
                         value: "{search_bar}",
                         oninput: move |evt| { search_bar.set(evt.value()); selected_item_index.set(0); },
                         autofocus: true,
                     }
-                    div { class: "text-gray-500 text-sm font-mono", "{filtered_items.read().len()} items" }
+
+                    // Pinboard Switcher
+                    div {
+                        class: "flex items-center gap-1 mx-4 text-sm",
+
+                        button {
+                            class: if active_pinboard.read().is_none() { "px-3 py-1 rounded-full bg-[#007acc] text-white" } else { "px-3 py-1 rounded-full text-gray-400 hover:bg-white/10" },
+                            onclick: move |_| { active_pinboard.set(None); selected_item_index.set(0); },
+                            "History"
+                        }
+
+                        for pinboard in pinboards.read().iter().cloned() {
+                            button {
+                                key: "{pinboard.id}",
+                                class: if *active_pinboard.read() == Some(pinboard.id) { "px-3 py-1 rounded-full bg-[#007acc] text-white" } else { "px-3 py-1 rounded-full text-gray-400 hover:bg-white/10" },
+                                onclick: move |_| { active_pinboard.set(Some(pinboard.id)); selected_item_index.set(0); },
+                                "{pinboard.name}"
+                            }
+                        }
+
+                        if let Some(name) = new_pinboard_name() {
+                            input {
+                                class: "w-32 px-2 py-1 rounded bg-black/30 border border-white/10 outline-none text-white placeholder-gray-500",
+                                placeholder: "Pinboard name",
+                                value: "{name}",
+                                autofocus: true,
+                                oninput: move |evt| new_pinboard_name.set(Some(evt.value())),
+                                onkeydown: move |evt| {
+                                    // Keep the keys away from the list navigation
+                                    evt.stop_propagation();
+                                    match evt.key() {
+                                        Key::Enter => create_pinboard.call(new_pinboard_name().unwrap_or_default()),
+                                        Key::Escape => new_pinboard_name.set(None),
+                                        _ => {}
+                                    }
+                                },
+                            }
+                        } else {
+                            button {
+                                class: "px-2 py-1 rounded-full text-gray-400 hover:bg-white/10",
+                                title: "New pinboard",
+                                onclick: move |_| new_pinboard_name.set(Some(String::new())),
+                                "+"
+                            }
+                        }
+
+                        if let Some(pinboard_id) = active_pinboard() {
+                            button {
+                                class: "px-2 py-1 rounded-full text-gray-400 hover:bg-white/10",
+                                title: "Delete pinboard",
+                                onclick: move |_| {
+                                    if let Err(err) = store.read().delete_pinboard(pinboard_id) {
+                                        report_error.call(format!("Failed to delete the pinboard: {}", err));
+                                        return;
+                                    }
+                                    active_pinboard.set(None);
+                                    selected_item_index.set(0);
+                                    reload_items.call(());
+                                },
+                                "🗑"
+                            }
+                        }
+                    }
+
+                    // Capture Toggle: nothing copied is saved while paused
+                    if let Some(indicator) = capture_indicator() {
+                        button {
+                            class: "mr-3 px-3 py-1 rounded-full bg-amber-500 text-black text-sm font-semibold hover:bg-amber-400",
+                            title: "Resume capture",
+                            onclick: move |_| {
+                                clipboard::resume_capture();
+                                capture_indicator.set(None);
+                            },
+                            "⏸ {indicator} · Resume"
+                        }
+                    } else {
+                        select {
+                            class: "w-6 mr-3 text-sm bg-transparent text-gray-400 opacity-60 hover:opacity-100 outline-none cursor-pointer",
+                            title: "Pause capture",
+                            value: "",
+                            onchange: move |evt| {
+                                match evt.value().as_str() {
+                                    "" => return,
+                                    "until-resumed" => clipboard::pause_capture(None),
+                                    secs => match secs.parse::<u64>() {
+                                        Ok(secs) => clipboard::pause_capture(Some(Duration::from_secs(secs))),
+                                        Err(_) => return,
+                                    },
+                                }
+                                capture_indicator.set(capture_label(clipboard::capture_state()));
+                            },
+                            option { value: "", disabled: true, "⏸" }
+                            option { value: "60", "Pause for 1 minute" }
+                            option { value: "900", "Pause for 15 minutes" }
+                            option { value: "until-resumed", "Pause until resumed" }
+                        }
+                    }
+
+                    // Backup Restorer: the current history is backed up before it is replaced
+                    if !backups.read().is_empty() {
+                        select {
+                            class: "w-6 mr-3 text-sm bg-transparent text-gray-400 opacity-60 hover:opacity-100 outline-none cursor-pointer",
+                            title: "Restore a backup",
+                            value: "",
+                            onchange: move |evt| {
+                                if let Ok(index) = evt.value().parse::<usize>() {
+                                    let path = backups.peek().get(index).map(|backup| backup.path.clone());
+                                    if let Some(path) = path {
+                                        restore_backup.call(path);
+                                    }
+                                }
+                            },
+                            option { value: "", disabled: true, "⟲" }
+                            for (index, backup) in backups.read().iter().enumerate() {
+                                option {
+                                    key: "{backup.path.display()}",
+                                    value: "{index}",
+                                    "Restore backup from {humanize_time(backup.created_at)}"
+                                }
+                            }
+                        }
+                    }
+
+                    if search_bar.read().trim().is_empty() && active_pinboard.read().is_none() {
+                        div { class: "text-gray-500 text-sm font-mono", "{total_items} items" }
+                    } else {
+                        div { class: "text-gray-500 text-sm font-mono", "{filtered_items.read().len()} items" }
+                    }
                 }
 
                 // Body (Items)
                 div {
                     class: "flex-1 w-full overflow-x-auto overflow-y-hidden flex flex-row items-center gap-5 px-6 scrollbar-hide bg-[#1e1e1e]",
+                    onscroll: move |evt| {
+                        let distance_to_end = evt.scroll_width() as f64 - evt.client_width() as f64 - evt.scroll_left();
+                        if distance_to_end < LOAD_MORE_THRESHOLD {
+                            load_more.call(());
+                        }
+                    },
 
                     if filtered_items.read().is_empty() {
                             div { class: "w-full text-center text-gray-500 text-xl", "No records found 🕵️‍♂️" }
                     } else {
                         {
-                            filtered_items.read().iter().enumerate().map(|(index, item)| {
-                                to_owned![do_paste, item];
-
-                                rsx! {
-                                    ClipboardCard {
-                                        key: "{item.id}",
-                                        index: index,
-                                        is_selected: index == *selected_item_index.read(),
-                                        item: item.clone(),
-                                        on_click: move |_| {
-                                            to_owned![do_paste];
-                                            if index == *selected_item_index.read() {
-                                                do_paste(item.clone());
-                                            } else {
-                                                selected_item_index.set(index);
-                                            }
-                                        }
-                                    }
-                                }
-                            })
+                            filtered_items.read().iter().enumerate().take(pinned_count()).map(render_card.clone())
+                        }
+
+                        // Separator between the pinned section and the history
+                        if pinned_count() > 0 && pinned_count() < filtered_items.read().len() {
+                            div { class: "flex-shrink-0 w-px h-[140px] bg-white/20" }
+                        }
+
+                        {
+                            filtered_items.read().iter().enumerate().skip(pinned_count()).map(render_card.clone())
                         }
                     }
                 }
@@ -400,6 +1032,31 @@ fn Paste() -> Element {
                             span { "← →" }
                             span { class: "opacity-80", "Select" }
                         }
+
+                        div { class: "flex items-center gap-1",
+                            span { "⌘P" }
+                            span { class: "opacity-80", "Pin" }
+                        }
+
+                        div { class: "flex items-center gap-1",
+                            span { "Tab" }
+                            span { class: "opacity-80", "Pinboard" }
+                        }
+
+                        div { class: "flex items-center gap-1",
+                            span { "⌘⌫" }
+                            span { class: "opacity-80", "Delete" }
+                        }
+
+                        div { class: "flex items-center gap-1",
+                            span { "⌘Z" }
+                            span { class: "opacity-80", "Undo" }
+                        }
+
+                        div { class: "flex items-center gap-1",
+                            span { "⌥⇧⌘V" }
+                            span { class: "opacity-80", "Pause" }
+                        }
                     }
 
                     span {
@@ -407,6 +1064,76 @@ fn Paste() -> Element {
                         "Paste for Rust"
                     }
                 }
+
+                // Context Menu: deletes the item, or every unpinned item like it
+                if let Some(menu) = context_menu() {
+                    div {
+                        class: "fixed min-w-[220px] py-1 rounded-lg bg-[#3c3c3c] border border-white/10 text-sm text-gray-200 shadow-2xl z-30",
+                        style: "left: {menu.x}px; top: {menu.y}px;",
+                        onclick: move |evt| {
+                            evt.stop_propagation();
+                            context_menu.set(None);
+                        },
+                        div {
+                            class: "px-3 py-1 hover:bg-[#007acc] cursor-pointer",
+                            onclick: {
+                                let item = menu.item.clone();
+                                move |_| delete_item.call(item.clone())
+                            },
+                            "Delete"
+                        }
+                        div { class: "my-1 h-px bg-white/10" }
+                        div {
+                            class: "px-3 py-1 hover:bg-[#007acc] cursor-pointer",
+                            onclick: {
+                                let source_app = menu.item.source_app.clone();
+                                move |_| delete_matching.call(RecordFilter { source_app: Some(source_app.clone()), ..Default::default() })
+                            },
+                            "Delete all from {menu.item.source_app}"
+                        }
+                        div {
+                            class: "px-3 py-1 hover:bg-[#007acc] cursor-pointer",
+                            onclick: {
+                                let content_type = menu.item.content_type.clone();
+                                move |_| delete_matching.call(RecordFilter { content_type: Some(content_type.clone()), ..Default::default() })
+                            },
+                            "Delete all {menu.item.content_type.as_str().to_lowercase()} items"
+                        }
+                        div {
+                            class: "px-3 py-1 hover:bg-[#007acc] cursor-pointer",
+                            onclick: move |_| delete_matching.call(RecordFilter { since: Some(Utc::now() - TimeDelta::hours(1)), ..Default::default() }),
+                            "Delete the last hour"
+                        }
+                        div {
+                            class: "px-3 py-1 hover:bg-[#007acc] cursor-pointer",
+                            onclick: move |_| delete_matching.call(RecordFilter { since: Some(Utc::now() - TimeDelta::days(1)), ..Default::default() }),
+                            "Delete the last 24 hours"
+                        }
+                    }
+                }
+
+                // Toasts: non-fatal errors and undoable deletions, dismissed on click or after a while
+                div {
+                    class: "absolute bottom-8 right-4 flex flex-col items-end gap-2 z-20",
+                    for toast in toasts.read().iter().cloned() {
+                        div {
+                            key: "{toast.id}",
+                            class: if toast.kind == ToastKind::Error { "max-w-[360px] px-3 py-2 rounded-lg bg-red-600/90 text-sm text-white shadow-lg cursor-pointer" } else { "max-w-[360px] px-3 py-2 rounded-lg bg-[#3c3c3c] text-sm text-white shadow-lg cursor-pointer flex items-center gap-3" },
+                            onclick: move |_| toasts.write().retain(|t| t.id != toast.id),
+                            "{toast.message}"
+                            if let ToastKind::Undo(ids) = toast.kind.clone() {
+                                button {
+                                    class: "font-semibold text-[#3ea6ff] hover:underline",
+                                    onclick: move |evt| {
+                                        evt.stop_propagation();
+                                        undo_delete.call(ids.clone());
+                                    },
+                                    "Undo"
+                                }
+                            }
+                        }
+                    }
+                }
             }
         }
     }
@@ -418,6 +1145,13 @@ fn ClipboardCard(
     is_selected: bool,
     item: clipboard::Item,
     on_click: EventHandler<()>,
+    on_toggle_pin: EventHandler<clipboard::Item>,
+    /// Right click, with the position of the pointer in the window.
+    on_context_menu: EventHandler<(f64, f64)>,
+    pinboards: Vec<Pinboard>,
+    in_pinboard: bool,
+    on_add_to_pinboard: EventHandler<i64>,
+    on_remove_from_pinboard: EventHandler<()>,
 ) -> Element {
     let base_style = "flex-shrink-0 w-[240px] h-[180px] rounded-lg flex flex-col cursor-pointer relative overflow-hidden transition-all duration-200";
     let active_style = if is_selected {
@@ -425,11 +1159,20 @@ fn ClipboardCard(
     } else {
         "bg-[#2d2d2d] hover:bg-[#333333] opacity-80 hover:opacity-100"
     };
+    let color = (item.subtype == Some(ContentSubtype::Color))
+        .then(|| classifier::color_value(&item.content))
+        .flatten();
 
     rsx! {
         div {
             class: "{base_style} {active_style}",
             onclick: move |_| on_click.call(()),
+            oncontextmenu: move |evt: MouseEvent| {
+                evt.prevent_default();
+                evt.stop_propagation();
+                let position = evt.client_coordinates();
+                on_context_menu.call((position.x, position.y));
+            },
 
             // Header: SourceApp, RelativeTimestamp, Icon
             div {
@@ -439,16 +1182,74 @@ fn ClipboardCard(
                 div {
                     class: "flex flex-col justify-center",
                     span { class: "text-sm font-bold text-gray-200 truncate max-w-[180px]", "{item.source_app}" }
-                    span { class: "text-[10px] text-gray-500 font-mono mt-0.5", "{humanize_time(item.timestamp)}" }
+                    div {
+                        class: "flex items-center gap-1 mt-0.5",
+                        span {
+                            class: "text-[10px] text-gray-500 font-mono",
+                            title: "Copied {item.copy_count}×, pasted {item.paste_count}×, first copied {humanize_time(item.created_at)}",
+                            "{humanize_time(item.timestamp)}"
+                        }
+                        if let Some(subtype) = item.subtype.as_ref().filter(|subtype| **subtype != ContentSubtype::Prose) {
+                            span { class: "px-1 rounded bg-white/10 text-[10px] text-gray-400", "{subtype}" }
+                        }
+                        for secret in item.secrets.iter() {
+                            span { class: "px-1 rounded bg-red-500/30 text-[10px] text-red-300", title: "Holds a secret, redacted or kept for a short while", "🔑 {secret}" }
+                        }
+                    }
                 }
 
-                // Right: App Icon
+                // Right: Pinboard Actions, Pin Toggle, App Icon
                 div {
-                    class: "w-8 h-8 rounded bg-white/10 p-1 flex items-center justify-center shadow-inner",
-                    img {
-                        class: "w-full h-full object-contain",
-                        alt: "App Icon",
-                        src: "{item.icon_path}"
+                    class: "flex items-center gap-2",
+
+                    if in_pinboard {
+                        button {
+                            class: "text-sm opacity-30 hover:opacity-80",
+                            title: "Remove from pinboard",
+                            onclick: move |evt: MouseEvent| {
+                                evt.stop_propagation();
+                                on_remove_from_pinboard.call(());
+                            },
+                            "✕"
+                        }
+                    } else if !pinboards.is_empty() {
+                        select {
+                            class: "w-6 text-sm bg-transparent opacity-30 hover:opacity-80 outline-none cursor-pointer",
+                            title: "Add to pinboard",
+                            value: "",
+                            onclick: move |evt: MouseEvent| evt.stop_propagation(),
+                            onchange: move |evt| {
+                                if let Ok(pinboard_id) = evt.value().parse::<i64>() {
+                                    on_add_to_pinboard.call(pinboard_id);
+                                }
+                            },
+                            option { value: "", disabled: true, "🗂" }
+                            for pinboard in pinboards.iter() {
+                                option { key: "{pinboard.id}", value: "{pinboard.id}", "{pinboard.name}" }
+                            }
+                        }
+                    }
+
+                    button {
+                        class: if item.pinned { "text-sm opacity-100" } else { "text-sm opacity-30 hover:opacity-80" },
+                        title: if item.pinned { "Unpin" } else { "Pin" },
+                        onclick: {
+                            to_owned![item];
+                            move |evt: MouseEvent| {
+                                evt.stop_propagation();
+                                on_toggle_pin.call(item.clone());
+                            }
+                        },
+                        "📌"
+                    }
+
+                    div {
+                        class: "w-8 h-8 rounded bg-white/10 p-1 flex items-center justify-center shadow-inner",
+                        img {
+                            class: "w-full h-full object-contain",
+                            alt: "App Icon",
+                            src: "{item.icon_path}"
+                        }
                     }
                 }
             }
@@ -456,8 +1257,48 @@ fn ClipboardCard(
             // Content
             div {
                 class: "flex-1 p-3 overflow-hidden text-xs text-gray-300 font-mono leading-relaxed break-all whitespace-pre-wrap [mask-image:linear-gradient(to_bottom,black_70%,transparent)]",
-                if item.content_type == ContentTypes::Text {
+                if let Some(color) = color {
+                    // Validated by the classifier, only a color value can get here
+                    div { class: "flex items-center gap-2",
+                        div { class: "w-8 h-8 rounded border border-white/20", style: "background-color: {color}" }
+                        "{&item.content}"
+                    }
+                } else if item.content_type == ContentTypes::Text {
                     "{&item.content}"
+                } else if item.content_type == ContentTypes::Html {
+                    // Sanitized by the backend, only formatting tags without attributes remain
+                    div {
+                        class: "font-sans whitespace-normal break-words",
+                        dangerous_inner_html: item.html.clone().unwrap_or_default()
+                    }
+                } else if item.content_type == ContentTypes::Files {
+                    div {
+                        class: "flex flex-col gap-1 font-sans whitespace-normal",
+                        for file in item.files.iter() {
+                            div {
+                                class: if file.exists { "flex items-center gap-2" } else { "flex items-center gap-2 opacity-50" },
+                                title: "{file.path.display()}",
+                                if let Some(icon_path) = &file.icon_path {
+                                    img {
+                                        class: "w-4 h-4 object-contain",
+                                        alt: "File Icon",
+                                        src: "{icon_path.display()}"
+                                    }
+                                } else {
+                                    span { if file.is_dir { "📁" } else { "📄" } }
+                                }
+                                span {
+                                    class: if file.exists { "flex-1 truncate" } else { "flex-1 truncate line-through" },
+                                    "{file.name()}"
+                                }
+                                if !file.exists {
+                                    span { class: "px-1 rounded bg-red-500/30 text-red-300", "Missing" }
+                                } else if let Some(size) = file.size {
+                                    span { class: "text-gray-500", "{humanize_size(size)}" }
+                                }
+                            }
+                        }
+                    }
                 } else if item.content_type == ContentTypes::Image {
                     img {
                         class: "w-full h-full object-contain block",
@@ -478,6 +1319,22 @@ fn ClipboardCard(
 // ------------------------------------------------------------------
 //                             INTERNAL
 // ------------------------------------------------------------------
+/// The label of the capture indicator, `None` while recording
+fn capture_label(state: CaptureState) -> Option<String> {
+    match state {
+        CaptureState::Recording => None,
+        CaptureState::Paused(None) => Some("Capture paused".to_string()),
+        CaptureState::Paused(Some(until)) => {
+            let left = (until - chrono::Utc::now()).num_seconds().max(0);
+            Some(format!(
+                "Capture paused, {}:{:02} left",
+                left / 60,
+                left % 60
+            ))
+        }
+    }
+}
+
 /// A helper function to set the visibility of a window
 fn set_window_visibility(name: &str, is_visible: bool) {
     if let Ok(mut registry) = WINDOW_REGISTRY.write() {